@@ -0,0 +1,357 @@
+//! Access-log ingestion for adaptive tarpit content.
+//!
+//! Parses Apache/Nginx combined-format access logs and feeds two pipelines
+//! from the same pass over the file: (1) the Markov word/sequence tables are
+//! trained on the requested paths and referrers, so tarpit output mimics the
+//! victim site's own URL vocabulary, and (2) a rolling, time-bucketed count of
+//! which paths are hit most is accumulated in memory and periodically flushed
+//! to Postgres, so `top_crawled_paths` can tell the tarpit generator which
+//! synthetic links should look like the hottest real endpoints.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use dbpool_rs::{with_retry, DbError, PgPool};
+use once_cell::sync::Lazy;
+use postgres::Client;
+use pyo3::prelude::*;
+use regex::Regex;
+
+const EMPTY_WORD_ID: i32 = 1;
+// Width of a popularity bucket; hits are merged into the bucket they land in
+// and only flushed to Postgres once that bucket's deadline (start + width)
+// has passed, so a burst within the same minute is a single upsert.
+const BUCKET_SECONDS: i64 = 60;
+
+// Shared pooled-connection layer (see dbpool_rs) so ingestion and path-lookup
+// no longer open a fresh `Client` per call, and transient failures (lost
+// connection, serialization failure, deadlock, admin shutdown) are retried
+// with backoff instead of surfacing as an opaque ingest failure.
+static DB_POOL: Lazy<Result<PgPool, String>> = Lazy::new(dbpool_rs::build_default_pool);
+
+fn pool() -> Result<&'static PgPool, DbError> {
+    DB_POOL.as_ref().map_err(|e| DbError::Fatal(e.clone()))
+}
+
+fn db_err_to_py(e: DbError) -> PyErr {
+    match e {
+        DbError::Transient(msg) => pyo3::exceptions::PyConnectionError::new_err(msg),
+        DbError::Fatal(msg) => pyo3::exceptions::PyRuntimeError::new_err(msg),
+    }
+}
+
+struct LogEntry {
+    #[allow(dead_code)]
+    ip: String,
+    path: String,
+    referrer: Option<String>,
+    timestamp: DateTime<Utc>,
+}
+
+// Combined log format: `ip - user [timestamp] "METHOD path HTTP/ver" status bytes "referrer" "ua"`
+static LOG_LINE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"^(?P<ip>\S+) \S+ \S+ \[(?P<ts>[^\]]+)\] "(?P<method>\S+) (?P<path>\S+) \S+" (?P<status>\d+) (?P<bytes>\S+) "(?P<referrer>[^"]*)" "(?P<ua>[^"]*)""#,
+    )
+    .unwrap()
+});
+
+fn parse_log_line(line: &str) -> Option<LogEntry> {
+    let caps = LOG_LINE_RE.captures(line)?;
+    let ts = DateTime::parse_from_str(&caps["ts"], "%d/%b/%Y:%H:%M:%S %z")
+        .ok()?
+        .with_timezone(&Utc);
+    let referrer = match &caps["referrer"] {
+        "" | "-" => None,
+        r => Some(r.to_string()),
+    };
+    Some(LogEntry {
+        ip: caps["ip"].to_string(),
+        path: caps["path"].to_string(),
+        referrer,
+        timestamp: ts,
+    })
+}
+
+fn tokenize_path(path: &str) -> Vec<String> {
+    path.split(|c: char| !c.is_alphanumeric())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_lowercase())
+        .collect()
+}
+
+fn get_word_id(txn: &mut postgres::Transaction, cache: &mut HashMap<String, i32>, word: &str) -> Result<i32, postgres::Error> {
+    if let Some(&id) = cache.get(word) {
+        return Ok(id);
+    }
+    if word.is_empty() {
+        cache.insert(String::new(), EMPTY_WORD_ID);
+        return Ok(EMPTY_WORD_ID);
+    }
+    if let Some(row) = txn.query_opt("SELECT id FROM markov_words WHERE word = $1", &[&word])? {
+        let id: i32 = row.get(0);
+        cache.insert(word.to_string(), id);
+        return Ok(id);
+    }
+    let row = txn.query_one(
+        "INSERT INTO markov_words (word) VALUES ($1) ON CONFLICT (word) DO UPDATE SET word=EXCLUDED.word RETURNING id",
+        &[&word],
+    )?;
+    let id: i32 = row.get(0);
+    cache.insert(word.to_string(), id);
+    Ok(id)
+}
+
+/// Upserts one `(p1, p2, next_id)` transition into the order-2 table and its
+/// order-1/unigram projections, so vocabulary learned from access logs is
+/// visible to the generation-time Katz back-off (`get_next_word_from_db` in
+/// tarpit-rs) the same way corpus-trained vocabulary is - without this, a
+/// log-trained install would have an order-2 table but no order-1/unigram
+/// fallback for it to back off onto.
+fn train_transition(txn: &mut postgres::Transaction, p1: i32, p2: i32, next_id: i32) -> Result<(), postgres::Error> {
+    txn.execute(
+        "INSERT INTO markov_sequences (p1, p2, next_id, freq) VALUES ($1, $2, $3, 1) ON CONFLICT (p1, p2, next_id) DO UPDATE SET freq = markov_sequences.freq + 1",
+        &[&p1, &p2, &next_id],
+    )?;
+    txn.execute(
+        "INSERT INTO markov_sequences_1 (p2, next_id, freq) VALUES ($1, $2, 1) ON CONFLICT (p2, next_id) DO UPDATE SET freq = markov_sequences_1.freq + 1",
+        &[&p2, &next_id],
+    )?;
+    txn.execute(
+        "INSERT INTO markov_unigrams (next_id, freq) VALUES ($1, 1) ON CONFLICT (next_id) DO UPDATE SET freq = markov_unigrams.freq + 1",
+        &[&next_id],
+    )?;
+    Ok(())
+}
+
+/// Trains one line's tokens as a single Postgres transaction, committed only
+/// once every word/transition upsert in the line has succeeded. `with_retry`
+/// reruns this whole function from the first word on a fresh connection after
+/// a transient failure, so without a transaction a partial line (some
+/// transitions already committed before the failure) would get its leading
+/// words' `freq` incremented twice on retry; wrapping it in one transaction
+/// means a failure anywhere in the line rolls back everything from that line.
+fn train_tokens(client: &mut Client, cache: &mut HashMap<String, i32>, words: &[String]) -> Result<(), postgres::Error> {
+    if words.is_empty() {
+        return Ok(());
+    }
+    let mut txn = client.transaction()?;
+    let mut p1 = EMPTY_WORD_ID;
+    let mut p2 = EMPTY_WORD_ID;
+    for word in words {
+        let next_id = get_word_id(&mut txn, cache, word)?;
+        train_transition(&mut txn, p1, p2, next_id)?;
+        p1 = p2;
+        p2 = next_id;
+    }
+    train_transition(&mut txn, p1, p2, EMPTY_WORD_ID)?;
+    txn.commit()?;
+    Ok(())
+}
+
+// Rolling popularity buckets, keyed by bucket start (unix seconds, truncated to
+// BUCKET_SECONDS). Each bucket is flushed to Postgres and dropped once "now"
+// has passed its deadline (bucket_start + BUCKET_SECONDS).
+static POPULARITY_BUCKETS: Lazy<Mutex<HashMap<i64, HashMap<String, i64>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn bucket_start(ts: DateTime<Utc>) -> i64 {
+    (ts.timestamp() / BUCKET_SECONDS) * BUCKET_SECONDS
+}
+
+fn record_hit(path: &str, ts: DateTime<Utc>) {
+    let mut buckets = POPULARITY_BUCKETS.lock().unwrap();
+    let bucket = buckets.entry(bucket_start(ts)).or_default();
+    *bucket.entry(path.to_string()).or_insert(0) += 1;
+}
+
+/// Re-merges `hits[from_idx..]` (rows already pulled out of
+/// `POPULARITY_BUCKETS` by `flush_due_buckets` but never written to Postgres)
+/// back into the bucket for `start`, so a later `with_retry` attempt has
+/// something to retry instead of silently losing those counts. Merges (rather
+/// than overwrites) in case a concurrent `record_hit` has since recreated the
+/// bucket.
+fn requeue_unflushed_hits(start: i64, hits: &[(String, i64)], from_idx: usize) {
+    let mut buckets = POPULARITY_BUCKETS.lock().unwrap();
+    let bucket = buckets.entry(start).or_default();
+    for (p, c) in &hits[from_idx..] {
+        *bucket.entry(p.clone()).or_insert(0) += c;
+    }
+}
+
+fn flush_due_buckets(client: &mut Client, now: DateTime<Utc>) -> Result<(), postgres::Error> {
+    let now_secs = now.timestamp();
+    let due: Vec<i64> = {
+        let buckets = POPULARITY_BUCKETS.lock().unwrap();
+        buckets
+            .keys()
+            .filter(|&&start| start + BUCKET_SECONDS <= now_secs)
+            .copied()
+            .collect()
+    };
+    for start in due {
+        let hits = {
+            let mut buckets = POPULARITY_BUCKETS.lock().unwrap();
+            buckets.remove(&start).unwrap_or_default()
+        };
+        let hits: Vec<(String, i64)> = hits.into_iter().collect();
+        for (idx, (path, count)) in hits.iter().enumerate() {
+            if let Err(e) = client.execute(
+                "INSERT INTO path_popularity (bucket_start, path, hits) VALUES (to_timestamp($1), $2, $3) \
+                 ON CONFLICT (bucket_start, path) DO UPDATE SET hits = path_popularity.hits + EXCLUDED.hits",
+                &[&(start as f64), path, count],
+            ) {
+                requeue_unflushed_hits(start, &hits, idx);
+                return Err(e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parses an Apache/Nginx combined access log at `path`, training the Markov
+/// corpus on requested paths/referrers and accumulating path-hit counts into
+/// the rolling popularity buckets (flushing any buckets whose deadline has
+/// already passed).
+#[pyfunction]
+fn ingest_access_log(path: String) -> PyResult<()> {
+    let pool = pool().map_err(db_err_to_py)?;
+    let file = File::open(&path).map_err(|e| pyo3::exceptions::PyIOError::new_err(format!("{}", e)))?;
+    let reader = BufReader::new(file);
+
+    let mut cache: HashMap<String, i32> = HashMap::new();
+    cache.insert(String::new(), EMPTY_WORD_ID);
+
+    for line in reader.lines() {
+        let line = line.map_err(|e| pyo3::exceptions::PyIOError::new_err(format!("{}", e)))?;
+        let Some(entry) = parse_log_line(&line) else { continue };
+
+        let mut tokens = tokenize_path(&entry.path);
+        if let Some(referrer) = &entry.referrer {
+            tokens.extend(tokenize_path(referrer));
+        }
+        with_retry(pool, |conn| {
+            // `get_word_id` caches a word's id as soon as it inserts it,
+            // before the surrounding transaction commits. If this attempt's
+            // transaction then fails and `with_retry` tries again, a stale
+            // cache hit would skip re-inserting a word whose row never
+            // actually committed, so snapshot the cache per attempt and roll
+            // it back alongside the transaction whenever that attempt fails.
+            let snapshot = cache.clone();
+            match train_tokens(conn, &mut cache, &tokens) {
+                Ok(()) => Ok(()),
+                Err(e) => {
+                    cache = snapshot;
+                    Err(e)
+                }
+            }
+        })
+        .map_err(db_err_to_py)?;
+
+        record_hit(&entry.path, entry.timestamp);
+    }
+
+    with_retry(pool, |conn| flush_due_buckets(conn, Utc::now())).map_err(db_err_to_py)?;
+    Ok(())
+}
+
+/// Returns the `limit` most-hit paths over the trailing `window` seconds,
+/// summed across the flushed popularity buckets in Postgres.
+#[pyfunction]
+fn top_crawled_paths(window: i64, limit: i64) -> PyResult<Vec<(String, i64)>> {
+    let pool = pool().map_err(db_err_to_py)?;
+    with_retry(pool, |conn| flush_due_buckets(conn, Utc::now())).map_err(db_err_to_py)?;
+
+    let rows = with_retry(pool, |conn| {
+        conn.query(
+            "SELECT path, SUM(hits)::bigint AS total FROM path_popularity \
+             WHERE bucket_start >= now() - ($1 || ' seconds')::interval \
+             GROUP BY path ORDER BY total DESC LIMIT $2",
+            &[&window.to_string(), &limit],
+        )
+    })
+    .map_err(db_err_to_py)?;
+
+    Ok(rows.iter().map(|r| (r.get(0), r.get(1))).collect())
+}
+
+#[pymodule]
+fn accesslog_rs(_py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(ingest_access_log, m)?)?;
+    m.add_function(wrap_pyfunction!(top_crawled_paths, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn parse_log_line_extracts_fields_from_a_well_formed_line() {
+        let line = r#"203.0.113.5 - - [10/Oct/2023:13:55:36 -0700] "GET /a/b?c=1 HTTP/1.1" 200 2326 "https://example.com/ref" "curl/8.0""#;
+        let entry = parse_log_line(line).unwrap();
+        assert_eq!(entry.ip, "203.0.113.5");
+        assert_eq!(entry.path, "/a/b?c=1");
+        assert_eq!(entry.referrer.as_deref(), Some("https://example.com/ref"));
+    }
+
+    #[test]
+    fn parse_log_line_treats_dash_and_empty_referrer_as_none() {
+        let line = r#"203.0.113.5 - - [10/Oct/2023:13:55:36 -0700] "GET / HTTP/1.1" 200 2326 "-" "curl/8.0""#;
+        assert_eq!(parse_log_line(line).unwrap().referrer, None);
+
+        let line = r#"203.0.113.5 - - [10/Oct/2023:13:55:36 -0700] "GET / HTTP/1.1" 200 2326 "" "curl/8.0""#;
+        assert_eq!(parse_log_line(line).unwrap().referrer, None);
+    }
+
+    #[test]
+    fn parse_log_line_rejects_malformed_lines() {
+        assert!(parse_log_line("not a log line").is_none());
+        assert!(parse_log_line("").is_none());
+    }
+
+    #[test]
+    fn bucket_start_floors_to_the_bucket_width() {
+        let ts = Utc.timestamp_opt(125, 0).unwrap();
+        assert_eq!(bucket_start(ts), 120);
+        let ts = Utc.timestamp_opt(120, 0).unwrap();
+        assert_eq!(bucket_start(ts), 120);
+        let ts = Utc.timestamp_opt(179, 0).unwrap();
+        assert_eq!(bucket_start(ts), 120);
+    }
+
+    #[test]
+    fn requeue_unflushed_hits_puts_back_only_the_unwritten_tail() {
+        // Use a start key no other test in this module touches.
+        let start = 9_999_999_000;
+        let hits = vec![("/a".to_string(), 1i64), ("/b".to_string(), 2i64), ("/c".to_string(), 3i64)];
+
+        // Row 0 ("/a") is presented as having already committed before the
+        // failure at index 1, so only "/b" and "/c" should be requeued.
+        requeue_unflushed_hits(start, &hits, 1);
+
+        let mut buckets = POPULARITY_BUCKETS.lock().unwrap();
+        let bucket = buckets.remove(&start).unwrap();
+        assert_eq!(bucket.get("/a"), None);
+        assert_eq!(bucket.get("/b"), Some(&2));
+        assert_eq!(bucket.get("/c"), Some(&3));
+    }
+
+    #[test]
+    fn requeue_unflushed_hits_merges_into_a_bucket_recreated_concurrently() {
+        let start = 9_999_998_000;
+        {
+            let mut buckets = POPULARITY_BUCKETS.lock().unwrap();
+            buckets.entry(start).or_default().insert("/a".to_string(), 5);
+        }
+        let hits = vec![("/a".to_string(), 1i64)];
+        requeue_unflushed_hits(start, &hits, 0);
+
+        let mut buckets = POPULARITY_BUCKETS.lock().unwrap();
+        let bucket = buckets.remove(&start).unwrap();
+        assert_eq!(bucket.get("/a"), Some(&6));
+    }
+}
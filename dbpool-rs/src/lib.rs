@@ -0,0 +1,129 @@
+//! Shared pooled-connection and retry layer for every Postgres-backed crate
+//! (`tarpit_rs`, `markov_train_rs`, `accesslog_rs`).
+//!
+//! Before this crate, each module opened a fresh `postgres::Client` per call
+//! and silently swallowed connection errors (`.ok()`, `unwrap_or(1)`), so a
+//! transient outage looked identical to "no content for this context" and
+//! every tarpit render paid full TCP+auth handshake latency. `PgPool` hands
+//! out pooled connections instead, and [`with_retry`] classifies failures by
+//! their PostgreSQL `SqlState` so callers can retry transient errors with
+//! backoff while failing fast - with a typed, distinguishable error - on
+//! programming/constraint errors that retrying would never fix.
+
+use std::env;
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+use postgres::{Error as PgError, NoTls};
+use r2d2::Pool;
+use r2d2_postgres::PostgresConnectionManager;
+
+pub type PgPool = Pool<PostgresConnectionManager<NoTls>>;
+pub type PgConn = r2d2::PooledConnection<PostgresConnectionManager<NoTls>>;
+
+const DEFAULT_POOL_SIZE: u32 = 8;
+const MAX_RETRIES: u32 = 3;
+const BASE_BACKOFF_MS: u64 = 50;
+
+/// Error surfaced from [`with_retry`]: `Transient` means the retries were
+/// exhausted on a class of error (connection loss, serialization failure,
+/// deadlock, admin shutdown) that a later attempt could plausibly succeed at;
+/// `Fatal` means the error was a programming or constraint error that will
+/// fail the same way every time, so callers should stop retrying and surface
+/// it immediately rather than masking it as empty content.
+#[derive(Debug)]
+pub enum DbError {
+    Transient(String),
+    Fatal(String),
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::Transient(msg) => write!(f, "transient database error: {}", msg),
+            DbError::Fatal(msg) => write!(f, "database error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for DbError {}
+
+fn get_pg_password() -> Option<String> {
+    let path = env::var("PG_PASSWORD_FILE").unwrap_or_else(|_| "/run/secrets/pg_password".into());
+    std::fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+fn connection_string() -> String {
+    let host = env::var("PG_HOST").unwrap_or_else(|_| "localhost".into());
+    let port = env::var("PG_PORT").unwrap_or_else(|_| "5432".into());
+    let db = env::var("PG_DBNAME").unwrap_or_else(|_| "markovdb".into());
+    let user = env::var("PG_USER").unwrap_or_else(|_| "markovuser".into());
+    let password = get_pg_password().unwrap_or_default();
+    format!("host={} port={} dbname={} user={} password={}", host, port, db, user, password)
+}
+
+/// Builds a connection pool sized by `max_size` (defaults to
+/// [`DEFAULT_POOL_SIZE`] via [`build_default_pool`]). Building the pool itself
+/// is not retried - a misconfigured connection string should fail loudly at
+/// startup rather than hang behind silent retries.
+pub fn build_pool(max_size: u32) -> Result<PgPool, String> {
+    let conn_str = connection_string();
+    let config = conn_str.parse().map_err(|e| format!("invalid connection string: {}", e))?;
+    let manager = PostgresConnectionManager::new(config, NoTls);
+    Pool::builder()
+        .max_size(max_size)
+        .build(manager)
+        .map_err(|e| format!("failed to build connection pool: {}", e))
+}
+
+pub fn build_default_pool() -> Result<PgPool, String> {
+    build_pool(DEFAULT_POOL_SIZE)
+}
+
+/// Classifies a `postgres::Error`'s `SqlState` into the transient classes
+/// worth retrying: connection exceptions (`08xxx`), serialization failure
+/// (`40001`), deadlock (`40P01`), and admin shutdown (`57P01`). Errors with no
+/// `SqlState` at all (e.g. a dropped socket surfaced as an I/O error) are also
+/// treated as transient, since those are exactly the failures retrying helps
+/// with. Everything else - constraint violations, syntax errors, permission
+/// errors - is fatal: retrying would just reproduce the same failure.
+pub fn is_transient(err: &PgError) -> bool {
+    match err.code() {
+        Some(code) => {
+            let s = code.code();
+            s.starts_with("08") || s == "40001" || s == "40P01" || s == "57P01"
+        }
+        None => true,
+    }
+}
+
+/// Runs `op` against a pooled connection, retrying up to [`MAX_RETRIES`] times
+/// with exponential backoff when the failure is transient per [`is_transient`],
+/// and failing fast with [`DbError::Fatal`] otherwise.
+pub fn with_retry<T>(
+    pool: &PgPool,
+    mut op: impl FnMut(&mut PgConn) -> Result<T, PgError>,
+) -> Result<T, DbError> {
+    let mut attempt = 0u32;
+    loop {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(e) if attempt < MAX_RETRIES => {
+                attempt += 1;
+                thread::sleep(Duration::from_millis(BASE_BACKOFF_MS * 2u64.pow(attempt)));
+                continue;
+            }
+            Err(e) => return Err(DbError::Transient(e.to_string())),
+        };
+        match op(&mut conn) {
+            Ok(value) => return Ok(value),
+            Err(e) if is_transient(&e) && attempt < MAX_RETRIES => {
+                attempt += 1;
+                thread::sleep(Duration::from_millis(BASE_BACKOFF_MS * 2u64.pow(attempt)));
+            }
+            Err(e) if is_transient(&e) => return Err(DbError::Transient(e.to_string())),
+            Err(e) => return Err(DbError::Fatal(e.to_string())),
+        }
+    }
+}
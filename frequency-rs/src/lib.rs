@@ -76,10 +76,199 @@ fn get_realtime_frequency_features(ip: String, db: u32, window_seconds: u64, pre
     }
 }
 
+// Sentinel used for shape statistics that need at least two timestamps to be
+// meaningful (a single request has no inter-arrival gap to measure).
+const INSUFFICIENT_DATA: f64 = -1.0;
+const ENTROPY_BINS: usize = 6;
+// Upper edge of the smallest log-spaced gap bin, in seconds; gaps are bucketed
+// by doubling this repeatedly, so robotic fixed-cadence polling (near-identical
+// gaps) collapses into one or two bins and a low entropy score.
+const ENTROPY_BASE_BIN_SECONDS: f64 = 0.05;
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn std_dev(values: &[f64], mean_value: f64) -> f64 {
+    let variance = values.iter().map(|v| (v - mean_value).powi(2)).sum::<f64>() / values.len() as f64;
+    variance.sqrt()
+}
+
+/// Buckets a gap into one of `ENTROPY_BINS` log-spaced bins and returns the Shannon
+/// entropy (in bits) of the resulting distribution. Mechanical, fixed-cadence polling
+/// produces near-identical gaps that collapse into one bin, so entropy stays low;
+/// organic human traffic spreads across several bins, raising entropy.
+fn gap_entropy(gaps: &[f64]) -> f64 {
+    let mut counts = vec![0usize; ENTROPY_BINS];
+    for &gap in gaps {
+        let mut bin = 0usize;
+        let mut edge = ENTROPY_BASE_BIN_SECONDS;
+        while gap > edge && bin < ENTROPY_BINS - 1 {
+            edge *= 2.0;
+            bin += 1;
+        }
+        counts[bin] += 1;
+    }
+    let total = gaps.len() as f64;
+    -counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / total;
+            p * p.log2()
+        })
+        .sum::<f64>()
+}
+
+/// Returns the largest count of `timestamps` entries found within any
+/// 1-second window. `timestamps` must already be sorted ascending, so the
+/// largest count can be found with a single forward-advancing two-pointer
+/// sweep instead of a full nested scan: `right` only ever moves forward
+/// across the whole loop, so the combined work is O(n).
+fn max_hits_in_one_second(timestamps: &[f64]) -> i64 {
+    let mut burstiness: i64 = 0;
+    let mut right = 0usize;
+    for left in 0..timestamps.len() {
+        if right < left {
+            right = left;
+        }
+        while right < timestamps.len() && timestamps[right] < timestamps[left] + 1.0 {
+            right += 1;
+        }
+        burstiness = burstiness.max((right - left) as i64);
+    }
+    burstiness
+}
+
+/// Computes shape features of an IP's arrival process from the same sliding
+/// window used by [`query_frequency`]: mean/std/coefficient-of-variation of
+/// inter-arrival gaps, a 1-second burstiness peak, and the Shannon entropy of
+/// log-binned gaps. Runs in O(n) over the window's timestamp entries.
+///
+/// This reads the window rather than recording a new hit in it — the entry
+/// for the current request is written once by [`query_frequency`]'s `ZADD`.
+/// Callers that want both frequency and behavior features for one request
+/// must call `get_realtime_frequency_features` first so that entry exists;
+/// calling this alone will compute stats over only the *previous* hits.
+fn query_behavior_features(
+    ip: &str,
+    db: u32,
+    window_seconds: u64,
+    prefix: &str,
+    ttl: u64,
+) -> redis::RedisResult<(f64, f64, f64, i64, f64)> {
+    let mut con = get_connection(db)?;
+    let now = chrono::Utc::now().timestamp_micros() as f64 / 1_000_000.0;
+    let window_start = now - window_seconds as f64;
+    let key = format!("{}{}", prefix, ip);
+
+    let mut pipe = redis::pipe();
+    pipe.cmd("ZREMRANGEBYSCORE")
+        .arg(&key)
+        .arg("-inf")
+        .arg(format!("({}", window_start))
+        .cmd("ZRANGE")
+        .arg(&key)
+        .arg(window_start)
+        .arg(now)
+        .arg("BYSCORE")
+        .arg("WITHSCORES")
+        .cmd("EXPIRE")
+        .arg(&key)
+        .arg(ttl);
+    let results: Vec<Value> = pipe.query(&mut con)?;
+
+    let entries: Vec<(String, f64)> = if results.len() > 1 { FromRedisValue::from_redis_value(&results[1]).unwrap_or_default() } else { vec![] };
+    let mut timestamps: Vec<f64> = entries.iter().map(|(_, score)| *score).collect();
+    timestamps.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    if timestamps.len() < 2 {
+        return Ok((INSUFFICIENT_DATA, INSUFFICIENT_DATA, INSUFFICIENT_DATA, 0, INSUFFICIENT_DATA));
+    }
+
+    let gaps: Vec<f64> = timestamps.windows(2).map(|w| w[1] - w[0]).collect();
+    let gap_mean = mean(&gaps);
+    let gap_std = std_dev(&gaps, gap_mean);
+    let coefficient_of_variation = if gap_mean > 0.0 { gap_std / gap_mean } else { 0.0 };
+
+    let burstiness = max_hits_in_one_second(&timestamps);
+    let entropy = gap_entropy(&gaps);
+
+    Ok((gap_mean, gap_std, coefficient_of_variation, burstiness, entropy))
+}
+
+#[pyfunction]
+fn get_realtime_behavior_features(
+    ip: String,
+    db: u32,
+    window_seconds: u64,
+    prefix: String,
+    ttl: u64,
+) -> PyResult<(f64, f64, f64, i64, f64)> {
+    match query_behavior_features(&ip, db, window_seconds, &prefix, ttl) {
+        Ok(res) => Ok(res),
+        Err(e) => Err(pyo3::exceptions::PyRuntimeError::new_err(format!("Redis error: {}", e))),
+    }
+}
+
 #[pymodule]
 fn frequency_rs(_py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(get_realtime_frequency_features, m)?)?;
+    m.add_function(wrap_pyfunction!(get_realtime_behavior_features, m)?)?;
     Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mean_and_std_dev_of_uniform_values_is_zero_spread() {
+        let values = [2.0, 2.0, 2.0];
+        let m = mean(&values);
+        assert_eq!(m, 2.0);
+        assert_eq!(std_dev(&values, m), 0.0);
+    }
+
+    #[test]
+    fn std_dev_reflects_spread() {
+        let values = [1.0, 2.0, 3.0, 4.0];
+        let m = mean(&values);
+        assert_eq!(m, 2.5);
+        assert!((std_dev(&values, m) - 1.118).abs() < 0.001);
+    }
+
+    #[test]
+    fn gap_entropy_is_zero_for_identical_gaps() {
+        let gaps = [0.01, 0.01, 0.01, 0.01];
+        assert_eq!(gap_entropy(&gaps), 0.0);
+    }
+
+    #[test]
+    fn gap_entropy_is_higher_for_spread_out_gaps() {
+        let robotic = [0.01, 0.01, 0.01, 0.01];
+        let organic = [0.01, 0.2, 1.5, 10.0];
+        assert!(gap_entropy(&organic) > gap_entropy(&robotic));
+    }
+
+    #[test]
+    fn max_hits_in_one_second_counts_the_densest_window() {
+        // Three hits within the same second, then one isolated a long time later.
+        let timestamps = [0.0, 0.2, 0.9, 5.0];
+        assert_eq!(max_hits_in_one_second(&timestamps), 3);
+    }
+
+    #[test]
+    fn max_hits_in_one_second_handles_evenly_spaced_hits() {
+        let timestamps = [0.0, 2.0, 4.0, 6.0];
+        assert_eq!(max_hits_in_one_second(&timestamps), 1);
+    }
+
+    #[test]
+    fn max_hits_in_one_second_handles_empty_and_single_entry() {
+        assert_eq!(max_hits_in_one_second(&[]), 0);
+        assert_eq!(max_hits_in_one_second(&[3.0]), 1);
+    }
+}
+
 
@@ -6,6 +6,8 @@ use std::fs::{self, File};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
+const VLQ_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
 const DEFAULT_ARCHIVE_DIR: &str = "/app/fake_archives";
 const FILENAME_PREFIXES: [&str; 12] = [
     "analytics_bundle", "vendor_lib", "core_framework", "ui_component_pack",
@@ -51,30 +53,414 @@ fn generate_file_content(name: &str, target_size: usize) -> Vec<u8> {
     bytes
 }
 
-#[pyfunction(signature = (num_files, output_dir = None))]
-fn create_fake_js_zip(num_files: usize, output_dir: Option<String>) -> PyResult<Option<String>> {
+// Produces content that looks like a (very large) minified JS file but is
+// actually a long run of a single repeated byte wrapped in a block comment,
+// so a scraper that naively decompresses every archive entry in this "file"
+// pays for `target_size` bytes of disk/memory while the entry itself
+// compresses down to almost nothing under Deflate.
+const BOMB_FILL_BYTE: u8 = b'0';
+
+fn generate_bomb_content(name: &str, target_size: usize) -> Vec<u8> {
+    let header = format!("// Fake module: {}\n/*!\n", name);
+    let footer = "\n*/\n";
+    let filler_len = target_size.saturating_sub(header.len() + footer.len());
+    let mut bytes = Vec::with_capacity(header.len() + filler_len + footer.len());
+    bytes.extend_from_slice(header.as_bytes());
+    bytes.extend(std::iter::repeat(BOMB_FILL_BYTE).take(filler_len));
+    bytes.extend_from_slice(footer.as_bytes());
+    bytes
+}
+
+/// Generates a random base64-VLQ-alphabet `mappings` string with `line_count`
+/// `;`-separated lines, each holding a handful of `,`-separated segments. The
+/// values are not semantically valid offsets - nothing here decodes a real
+/// transformation - but they are drawn from the correct alphabet and shaped
+/// like genuine webpack/esbuild output.
+fn generate_vlq_mappings(line_count: usize) -> String {
+    let mut rng = rand::thread_rng();
+    (0..line_count)
+        .map(|_| {
+            let segments = rng.gen_range(1..6);
+            (0..segments)
+                .map(|_| {
+                    let len = rng.gen_range(1..6);
+                    (0..len)
+                        .map(|_| VLQ_ALPHABET[rng.gen_range(0..VLQ_ALPHABET.len())] as char)
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+// A real debug id is a stable hash of the build; ours only needs to look like
+// one (lowercase hex, UUID-shaped) and tie a `.js`/`.js.map` pair together via
+// the manifest.
+fn generate_debug_id() -> String {
+    let mut rng = rand::thread_rng();
+    let bytes: [u8; 16] = rng.gen();
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+/// Builds a plausible `.js.map` (Source Map v3) body for `js_name`, embedding
+/// `debug_id` under both the modern `debugId` field and the legacy
+/// `debug_id` field some tooling still reads.
+fn generate_source_map(js_name: &str, debug_id: &str) -> Vec<u8> {
+    let stem = js_name.trim_end_matches(".js");
+    let map = serde_json::json!({
+        "version": 3,
+        "file": js_name,
+        "sources": [format!("webpack:///./src/{}.js", stem)],
+        "sourcesContent": [serde_json::Value::Null],
+        "names": [],
+        "mappings": generate_vlq_mappings(rand::thread_rng().gen_range(20..80)),
+        "debugId": debug_id,
+        "debug_id": debug_id,
+    });
+    serde_json::to_vec_pretty(&map).unwrap_or_default()
+}
+
+/// Builds the top-level `manifest.json` body listing every generated file
+/// (`source` or `source_map`) alongside the debug id tying each pair
+/// together, mirroring the bundle manifests real frontend build tooling
+/// ships. Each file entry also carries its byte size and a
+/// `blake3-<hex>` integrity digest of its content - the same shape as a
+/// Subresource Integrity attribute or a package-lock entry - and the
+/// manifest as a whole carries a `manifestIntegrity` digest over the
+/// serialized `files` array, so a caller (or a scraper!) can verify every
+/// entry, and the manifest itself, matches what was advertised.
+fn build_manifest(entries: &[(String, &str, String)], files: &[(String, Vec<u8>)]) -> Vec<u8> {
+    let per_file: Vec<_> = entries
+        .iter()
+        .zip(files.iter())
+        .map(|((name, kind, debug_id), (_, content))| {
+            serde_json::json!({
+                "name": name,
+                "type": kind,
+                "debugId": debug_id,
+                "size": content.len(),
+                "integrity": format!("blake3-{}", blake3::hash(content).to_hex()),
+            })
+        })
+        .collect();
+    let files_json = serde_json::to_vec(&per_file).unwrap_or_default();
+    let manifest = serde_json::json!({
+        "version": 1,
+        "files": per_file,
+        "manifestIntegrity": format!("blake3-{}", blake3::hash(&files_json).to_hex()),
+    });
+    serde_json::to_vec_pretty(&manifest).unwrap_or_default()
+}
+
+/// Generates the full set of `(name, content)` entries for a decoy bundle of
+/// `num_files` fake JS assets - each `.js` paired with a `.js.map` (see
+/// [`generate_source_map`]) - plus a trailing `manifest.json` (see
+/// [`build_manifest`]) tying them together. Shared by every archive writer
+/// ([`create_fake_js_zip`], [`create_fake_js_archive`]) so the container
+/// format never affects what's inside it.
+///
+/// When `bomb_ratio` is set (and greater than 1), every `.js` entry is
+/// generated by [`generate_bomb_content`] instead of [`generate_file_content`]
+/// (see [`create_fake_js_zip`] for why). Returns the entries, their total
+/// logical (uncompressed) size, and the `manifest.json` body (already present
+/// among the entries) as a standalone string so callers can serve it
+/// directly, e.g. as a `Subresource-Integrity`-style header or a fake
+/// `package-lock`.
+fn generate_archive_entries(num_files: usize, bomb_ratio: Option<u32>) -> PyResult<(Vec<(String, Vec<u8>)>, u64, String)> {
+    let mut logical_size: u64 = 0;
+    let mut files: Vec<(String, Vec<u8>)> = Vec::with_capacity(num_files * 2 + 1);
+    let mut manifest_entries: Vec<(String, &str, String)> = Vec::with_capacity(num_files * 2);
+    for _ in 0..num_files {
+        let name = generate_realistic_filename()?;
+        let base_size = rand::thread_rng().gen_range(5 * 1024..50 * 1024);
+        let mut content = match bomb_ratio {
+            Some(ratio) if ratio > 1 => generate_bomb_content(&name, base_size * ratio as usize),
+            _ => generate_file_content(&name, base_size),
+        };
+        let map_name = format!("{}.map", name);
+        content.extend_from_slice(format!("\n//# sourceMappingURL={}\n", map_name).as_bytes());
+        logical_size += content.len() as u64;
+
+        let debug_id = generate_debug_id();
+        let map_content = generate_source_map(&name, &debug_id);
+        logical_size += map_content.len() as u64;
+
+        manifest_entries.push((name.clone(), "source", debug_id.clone()));
+        manifest_entries.push((map_name.clone(), "source_map", debug_id));
+        files.push((name, content));
+        files.push((map_name, map_content));
+    }
+
+    let manifest_content = build_manifest(&manifest_entries, &files);
+    logical_size += manifest_content.len() as u64;
+    let manifest_json = String::from_utf8(manifest_content.clone())
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Manifest was not valid UTF-8: {}", e)))?;
+    files.push(("manifest.json".to_string(), manifest_content));
+
+    Ok((files, logical_size, manifest_json))
+}
+
+/// Highest Deflate level `flate2` (the baseline Deflate backend) accepts.
+/// [`resolve_compression`]'s `"max"` level of 24 is only valid when the `zip`
+/// crate is additionally built with the `deflate-zopfli` feature; this is the
+/// level [`start_file_with_fallback`] retries at if `start_file` rejects 24.
+const FALLBACK_DEFLATE_LEVEL: i32 = 9;
+
+/// Resolves the `compression` argument accepted by [`create_fake_js_zip`]
+/// into a [`zip::CompressionMethod`] plus an optional explicit compression
+/// level. `"max"` selects Deflate at the highest level, which - when this
+/// crate is built against a zip crate with the `deflate-zopfli` feature
+/// enabled - runs the stream through zopfli for the smallest possible output;
+/// this is the backend worth pairing with `bomb_ratio`, since maximizing the
+/// compression ratio on repetitive filler maximizes amplification. Without
+/// that feature enabled, [`start_file_with_fallback`] falls back to
+/// [`FALLBACK_DEFLATE_LEVEL`] rather than failing archive creation outright.
+fn resolve_compression(compression: Option<&str>) -> PyResult<(zip::CompressionMethod, Option<i32>)> {
+    match compression.map(|s| s.to_lowercase()).as_deref() {
+        None | Some("deflate") | Some("deflated") => Ok((zip::CompressionMethod::Deflated, None)),
+        Some("stored") => Ok((zip::CompressionMethod::Stored, None)),
+        Some("bzip2") => Ok((zip::CompressionMethod::Bzip2, None)),
+        Some("zstd") => Ok((zip::CompressionMethod::Zstd, None)),
+        Some("max") => Ok((zip::CompressionMethod::Deflated, Some(24))),
+        Some(other) => Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "Unknown compression method '{}'; expected one of stored, deflate, bzip2, zstd, max",
+            other
+        ))),
+    }
+}
+
+/// Starts `name` in `zip` with `options`, retrying at [`FALLBACK_DEFLATE_LEVEL`]
+/// if `options` requested the zopfli-only level 24 and this build of the `zip`
+/// crate rejects it (i.e. it wasn't built with `deflate-zopfli`). Any other
+/// `start_file` error - including an AES encryption request on a build without
+/// `aes-crypto` - is returned as-is; downgrading encryption strength silently
+/// would be a worse surprise than just failing loudly.
+fn start_file_with_fallback(
+    zip: &mut zip::ZipWriter<File>,
+    name: &str,
+    options: FileOptions,
+    requested_level: Option<i32>,
+) -> zip::result::ZipResult<()> {
+    match zip.start_file(name, options) {
+        Ok(()) => Ok(()),
+        Err(_) if requested_level == Some(24) => {
+            zip.start_file(name, options.compression_level(Some(FALLBACK_DEFLATE_LEVEL)))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Encryption backend selected by the `password` argument of
+/// [`create_fake_js_zip`] via [`resolve_encryption`].
+enum EncryptionChoice {
+    /// Legacy ZipCrypto stream cipher - weak, but still enough to make a
+    /// scraper either stall trying to crack it or give up and store junk.
+    ZipCrypto,
+    /// WinZip AES encryption at the given key strength.
+    Aes(zip::AesMode),
+}
+
+/// Resolves the `password` argument accepted by [`create_fake_js_zip`] into
+/// an [`EncryptionChoice`]. `strength` is one of `"zipcrypto"`, `"aes128"`,
+/// `"aes192"`, or `"aes256"`.
+fn resolve_encryption(strength: &str) -> PyResult<EncryptionChoice> {
+    match strength.to_lowercase().as_str() {
+        "zipcrypto" => Ok(EncryptionChoice::ZipCrypto),
+        "aes128" => Ok(EncryptionChoice::Aes(zip::AesMode::Aes128)),
+        "aes192" => Ok(EncryptionChoice::Aes(zip::AesMode::Aes192)),
+        "aes256" => Ok(EncryptionChoice::Aes(zip::AesMode::Aes256)),
+        other => Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "Unknown encryption strength '{}'; expected one of zipcrypto, aes128, aes192, aes256",
+            other
+        ))),
+    }
+}
+
+/// Writes a decoy zip of `num_files` fake JS assets to `output_dir` (or
+/// [`DEFAULT_ARCHIVE_DIR`]) and returns
+/// `(archive_path, logical_size, compressed_size, password, manifest)`.
+///
+/// Each `.js` entry ships with a matching `.js.map` (see
+/// [`generate_source_map`]) linked via a trailing `//# sourceMappingURL=`
+/// comment, and the archive gets a top-level `manifest.json` (see
+/// [`build_manifest`]) listing every file and its debug id - mimicking the
+/// multi-artifact layout real frontend build tooling ships, so a scraper
+/// that only harvests `.js` files misses the bundle's cross-references.
+///
+/// When `bomb_ratio` is set (and greater than 1), every `.js` entry is
+/// generated by [`generate_bomb_content`] instead of [`generate_file_content`],
+/// inflating its declared size by that factor with highly-compressible filler
+/// so the archive decompresses to far more bytes than it occupies on disk.
+/// This is opt-in: without `bomb_ratio`, entries are the same
+/// randomized-but-dense content as before, which does not amplify
+/// meaningfully under Deflate.
+///
+/// `compression` selects the per-entry backend via [`resolve_compression`]
+/// (`"stored"`, `"deflate"`, `"bzip2"`, `"zstd"`, or `"max"`); it defaults to
+/// Deflate, matching the method this function always used before.
+///
+/// `password` selects an encryption strength via [`resolve_encryption`]
+/// (`"zipcrypto"`, `"aes128"`, `"aes192"`, or `"aes256"`); when set, every
+/// entry is encrypted under a freshly generated passphrase, which is
+/// returned alongside the archive path so the caller can decide whether to
+/// leak it (e.g. in a linked fake "readme") or withhold it entirely. A
+/// scraper that downloads the archive either stalls trying to crack it or
+/// silently stores junk it can never index. Leaving `password` unset writes
+/// a plain archive, as before.
+///
+/// The returned `manifest` is the same `manifest.json` body shipped inside
+/// the archive (see [`build_manifest`]), handed back as a string so the
+/// caller can serve a matching `Subresource-Integrity`-style header or a
+/// fake `package-lock` without re-opening the archive.
+#[pyfunction(signature = (num_files, output_dir = None, bomb_ratio = None, compression = None, password = None))]
+fn create_fake_js_zip(
+    num_files: usize,
+    output_dir: Option<String>,
+    bomb_ratio: Option<u32>,
+    compression: Option<String>,
+    password: Option<String>,
+) -> PyResult<Option<(String, u64, u64, Option<String>, String)>> {
     let out_dir = output_dir.unwrap_or_else(|| DEFAULT_ARCHIVE_DIR.to_string());
     fs::create_dir_all(&out_dir).map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to create dir: {}", e)))?;
     let timestamp = Utc::now().format("%Y%m%d_%H%M%S");
     let archive_path = Path::new(&out_dir).join(format!("assets_{}.zip", timestamp));
     let file = File::create(&archive_path).map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to create zip: {}", e)))?;
     let mut zip = zip::ZipWriter::new(file);
-    let options = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
-    for _ in 0..num_files {
-        let name = generate_realistic_filename()?;
-        let size = rand::thread_rng().gen_range(5 * 1024..50 * 1024);
-        let content = generate_file_content(&name, size);
-        zip.start_file(name, options).map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Zip error: {}", e)))?;
-        zip.write_all(&content).map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Write error: {}", e)))?;
+    let (method, level) = resolve_compression(compression.as_deref())?;
+    let mut options = FileOptions::default().compression_method(method);
+    if let Some(level) = level {
+        options = options.compression_level(Some(level));
+    }
+    let encryption = password.as_deref().map(resolve_encryption).transpose()?;
+    let generated_password = encryption.as_ref().map(|_| rand_string(20));
+    if let (Some(encryption), Some(pw)) = (&encryption, &generated_password) {
+        options = match encryption {
+            EncryptionChoice::ZipCrypto => options.with_deprecated_encryption(pw.as_bytes()),
+            EncryptionChoice::Aes(mode) => options.with_aes_encryption(*mode, pw),
+        };
+    }
+    let (files, logical_size, manifest) = generate_archive_entries(num_files, bomb_ratio)?;
+    for (name, content) in &files {
+        start_file_with_fallback(&mut zip, name, options, level)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Zip error: {}", e)))?;
+        zip.write_all(content).map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Write error: {}", e)))?;
     }
     zip.finish().map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Finish zip error: {}", e)))?;
-    Ok(Some(archive_path.to_string_lossy().to_string()))
+    let compressed_size = fs::metadata(&archive_path)
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to stat zip: {}", e)))?
+        .len();
+    Ok(Some((archive_path.to_string_lossy().to_string(), logical_size, compressed_size, generated_password, manifest)))
+}
+
+/// Container format for [`create_fake_js_archive`], selected via
+/// [`resolve_archive_format`].
+enum ArchiveFormat {
+    Tar,
+    TarGz,
+    TarBz2,
+}
+
+/// Resolves the `format` argument accepted by [`create_fake_js_archive`].
+/// `format` is one of `"tar"`, `"tar.gz"` (alias `"tgz"`), or `"tar.bz2"`
+/// (alias `"tbz2"`); it defaults to plain `"tar"`.
+fn resolve_archive_format(format: Option<&str>) -> PyResult<ArchiveFormat> {
+    match format.map(|s| s.to_lowercase()).as_deref() {
+        None | Some("tar") => Ok(ArchiveFormat::Tar),
+        Some("tar.gz") | Some("tgz") => Ok(ArchiveFormat::TarGz),
+        Some("tar.bz2") | Some("tbz2") => Ok(ArchiveFormat::TarBz2),
+        Some(other) => Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "Unknown archive format '{}'; expected one of tar, tar.gz, tar.bz2",
+            other
+        ))),
+    }
+}
+
+/// Appends `files` to `builder` as regular files (mode `0o644`) owned by
+/// root, stamped with the current time - a plausible build-output tarball,
+/// not an attempt to preserve any real ownership.
+fn write_tar_entries<W: Write>(builder: &mut tar::Builder<W>, files: &[(String, Vec<u8>)]) -> std::io::Result<()> {
+    let mtime = Utc::now().timestamp().max(0) as u64;
+    for (name, content) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_path(name)?;
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        header.set_cksum();
+        builder.append_data(&mut header, name, content.as_slice())?;
+    }
+    Ok(())
+}
+
+/// Writes a decoy tarball of `num_files` fake JS assets to `output_dir` (or
+/// [`DEFAULT_ARCHIVE_DIR`]) and returns `(archive_path, logical_size,
+/// compressed_size, manifest)`, mirroring [`create_fake_js_zip`] but with the zip
+/// container swapped for a tar-family one.
+///
+/// Entries are the same `.js`/`.js.map`/`manifest.json` set produced by
+/// [`generate_archive_entries`] - only the container changes, so both
+/// functions share identical content and a scraper can't fingerprint decoys
+/// by container format alone.
+///
+/// `format` selects the container via [`resolve_archive_format`] (`"tar"`,
+/// `"tar.gz"`/`"tgz"`, or `"tar.bz2"`/`"tbz2"`; defaults to plain `"tar"`).
+///
+/// The returned `manifest` is the same `manifest.json` body shipped inside
+/// the archive (see [`build_manifest`]), handed back as a string - see
+/// [`create_fake_js_zip`] for why.
+#[pyfunction(signature = (num_files, output_dir = None, bomb_ratio = None, format = None))]
+fn create_fake_js_archive(
+    num_files: usize,
+    output_dir: Option<String>,
+    bomb_ratio: Option<u32>,
+    format: Option<String>,
+) -> PyResult<Option<(String, u64, u64, String)>> {
+    let out_dir = output_dir.unwrap_or_else(|| DEFAULT_ARCHIVE_DIR.to_string());
+    fs::create_dir_all(&out_dir).map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to create dir: {}", e)))?;
+    let archive_format = resolve_archive_format(format.as_deref())?;
+    let ext = match archive_format {
+        ArchiveFormat::Tar => "tar",
+        ArchiveFormat::TarGz => "tar.gz",
+        ArchiveFormat::TarBz2 => "tar.bz2",
+    };
+    let timestamp = Utc::now().format("%Y%m%d_%H%M%S");
+    let archive_path = Path::new(&out_dir).join(format!("assets_{}.{}", timestamp, ext));
+    let (files, logical_size, manifest) = generate_archive_entries(num_files, bomb_ratio)?;
+
+    let file = File::create(&archive_path).map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to create archive: {}", e)))?;
+    let tar_err = |e: std::io::Error| PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Tar error: {}", e));
+    match archive_format {
+        ArchiveFormat::Tar => {
+            let mut builder = tar::Builder::new(file);
+            write_tar_entries(&mut builder, &files).map_err(tar_err)?;
+            builder.finish().map_err(tar_err)?;
+        }
+        ArchiveFormat::TarGz => {
+            let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(file, flate2::Compression::default()));
+            write_tar_entries(&mut builder, &files).map_err(tar_err)?;
+            builder.into_inner().map_err(tar_err)?.finish().map_err(tar_err)?;
+        }
+        ArchiveFormat::TarBz2 => {
+            let mut builder = tar::Builder::new(bzip2::write::BzEncoder::new(file, bzip2::Compression::default()));
+            write_tar_entries(&mut builder, &files).map_err(tar_err)?;
+            builder.into_inner().map_err(tar_err)?.finish().map_err(tar_err)?;
+        }
+    }
+
+    let compressed_size = fs::metadata(&archive_path)
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to stat archive: {}", e)))?
+        .len();
+    Ok(Some((archive_path.to_string_lossy().to_string(), logical_size, compressed_size, manifest)))
 }
 
 #[pymodule]
 fn jszip_rs(_py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(generate_realistic_filename, m)?)?;
     m.add_function(wrap_pyfunction!(create_fake_js_zip, m)?)?;
+    m.add_function(wrap_pyfunction!(create_fake_js_archive, m)?)?;
     Ok(())
 }
 
@@ -82,6 +468,7 @@ fn jszip_rs(_py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
 mod tests {
     use super::*;
     use std::fs;
+    use std::io::Read;
 
     #[test]
     fn filename_varies() {
@@ -94,8 +481,202 @@ mod tests {
     #[test]
     fn zip_created() {
         let dir = tempfile::tempdir().unwrap();
-        let path = create_fake_js_zip(3, Some(dir.path().to_string_lossy().to_string())).unwrap().unwrap();
+        let (path, _logical_size, _compressed_size, password, _manifest) =
+            create_fake_js_zip(3, Some(dir.path().to_string_lossy().to_string()), None, None, None).unwrap().unwrap();
         assert!(PathBuf::from(&path).exists());
+        assert!(password.is_none());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn bomb_mode_amplifies_logical_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, logical_size, compressed_size, _password, _manifest) =
+            create_fake_js_zip(2, Some(dir.path().to_string_lossy().to_string()), Some(50), None, None)
+                .unwrap()
+                .unwrap();
+        assert!(logical_size > compressed_size * 10);
         fs::remove_file(path).unwrap();
     }
+
+    #[test]
+    fn archive_contains_source_maps_and_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, _logical_size, _compressed_size, _password, manifest_str) =
+            create_fake_js_zip(2, Some(dir.path().to_string_lossy().to_string()), None, None, None).unwrap().unwrap();
+        let archive = File::open(&path).unwrap();
+        let mut zip = zip::ZipArchive::new(archive).unwrap();
+        let names: Vec<String> = (0..zip.len()).map(|i| zip.by_index(i).unwrap().name().to_string()).collect();
+        assert!(names.iter().any(|n| n == "manifest.json"));
+        assert_eq!(names.iter().filter(|n| n.ends_with(".js.map")).count(), 2);
+
+        let manifest: serde_json::Value = {
+            let mut entry = zip.by_name("manifest.json").unwrap();
+            serde_json::from_reader(&mut entry).unwrap()
+        };
+        assert_eq!(manifest["files"].as_array().unwrap().len(), 4);
+        assert_eq!(serde_json::to_string_pretty(&manifest).unwrap(), manifest_str);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn manifest_hashes_match_the_stored_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, _logical_size, _compressed_size, _password, manifest_str) =
+            create_fake_js_zip(2, Some(dir.path().to_string_lossy().to_string()), None, None, None).unwrap().unwrap();
+        let manifest: serde_json::Value = serde_json::from_str(&manifest_str).unwrap();
+
+        let archive = File::open(&path).unwrap();
+        let mut zip = zip::ZipArchive::new(archive).unwrap();
+        let files = manifest["files"].as_array().unwrap();
+        for entry in files {
+            let name = entry["name"].as_str().unwrap();
+            let mut buf = Vec::new();
+            zip.by_name(name).unwrap().read_to_end(&mut buf).unwrap();
+            assert_eq!(entry["size"].as_u64().unwrap(), buf.len() as u64);
+            let expected = format!("blake3-{}", blake3::hash(&buf).to_hex());
+            assert_eq!(entry["integrity"].as_str().unwrap(), expected);
+        }
+
+        let files_json = serde_json::to_vec(files).unwrap();
+        let expected_manifest_digest = format!("blake3-{}", blake3::hash(&files_json).to_hex());
+        assert_eq!(manifest["manifestIntegrity"].as_str().unwrap(), expected_manifest_digest);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn every_compression_method_produces_a_readable_archive() {
+        for method in ["stored", "deflate", "bzip2", "zstd", "max"] {
+            let dir = tempfile::tempdir().unwrap();
+            let (path, _logical_size, _compressed_size, _password, _manifest) = create_fake_js_zip(
+                2,
+                Some(dir.path().to_string_lossy().to_string()),
+                None,
+                Some(method.to_string()),
+                None,
+            )
+            .unwrap()
+            .unwrap();
+            let archive = File::open(&path).unwrap();
+            let mut zip = zip::ZipArchive::new(archive).unwrap();
+            assert!(zip.len() > 0, "archive compressed with '{}' should contain entries", method);
+            for i in 0..zip.len() {
+                let mut entry = zip.by_index(i).unwrap();
+                let mut buf = Vec::new();
+                entry.read_to_end(&mut buf).unwrap_or_else(|e| panic!("failed to read entry under '{}': {}", method, e));
+            }
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn unknown_compression_method_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let result = create_fake_js_zip(
+            1,
+            Some(dir.path().to_string_lossy().to_string()),
+            None,
+            Some("rle".to_string()),
+            None,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn password_protected_archive_requires_the_generated_password() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, _logical_size, _compressed_size, password, _manifest) = create_fake_js_zip(
+            2,
+            Some(dir.path().to_string_lossy().to_string()),
+            None,
+            None,
+            Some("aes256".to_string()),
+        )
+        .unwrap()
+        .unwrap();
+        let password = password.expect("password-protected archive should return its generated password");
+
+        let archive = File::open(&path).unwrap();
+        let mut zip = zip::ZipArchive::new(archive).unwrap();
+        assert!(zip.by_index(0).is_err(), "entries should not open without a password");
+        let mut entry = zip
+            .by_index_decrypt(0, password.as_bytes())
+            .unwrap()
+            .expect("password should decrypt the first entry");
+        let mut buf = Vec::new();
+        entry.read_to_end(&mut buf).unwrap();
+        assert!(!buf.is_empty());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn unknown_encryption_strength_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let result = create_fake_js_zip(
+            1,
+            Some(dir.path().to_string_lossy().to_string()),
+            None,
+            None,
+            Some("rot13".to_string()),
+        );
+        assert!(result.is_err());
+    }
+
+    fn entry_count_of(path: &str) -> usize {
+        let file = File::open(path).unwrap();
+        let lower = path.to_lowercase();
+        let archive: Box<dyn Read> = if lower.ends_with(".tar.gz") {
+            Box::new(flate2::read::GzDecoder::new(file))
+        } else if lower.ends_with(".tar.bz2") {
+            Box::new(bzip2::read::BzDecoder::new(file))
+        } else {
+            Box::new(file)
+        };
+        tar::Archive::new(archive).entries().unwrap().count()
+    }
+
+    #[test]
+    fn every_archive_format_round_trips_to_the_expected_file_count() {
+        for format in ["tar", "tar.gz", "tgz", "tar.bz2", "tbz2"] {
+            let dir = tempfile::tempdir().unwrap();
+            let (path, _logical_size, _compressed_size, _manifest) = create_fake_js_archive(
+                2,
+                Some(dir.path().to_string_lossy().to_string()),
+                None,
+                Some(format.to_string()),
+            )
+            .unwrap()
+            .unwrap();
+            // 2 `.js` + 2 `.js.map` + manifest.json
+            assert_eq!(entry_count_of(&path), 5, "format '{}' should unpack to 5 entries", format);
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn tar_bomb_mode_amplifies_logical_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, logical_size, compressed_size, _manifest) = create_fake_js_archive(
+            2,
+            Some(dir.path().to_string_lossy().to_string()),
+            Some(50),
+            Some("tar.gz".to_string()),
+        )
+        .unwrap()
+        .unwrap();
+        assert!(logical_size > compressed_size * 10);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn unknown_archive_format_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let result = create_fake_js_archive(
+            1,
+            Some(dir.path().to_string_lossy().to_string()),
+            None,
+            Some("rar".to_string()),
+        );
+        assert!(result.is_err());
+    }
 }
@@ -24,8 +24,34 @@ fn filename_has_js_extension() {
 #[test]
 fn zip_file_created() {
     let dir = tempfile::tempdir().unwrap();
-    let path = create_fake_js_zip(1, Some(dir.path().to_string_lossy().to_string()))
-        .unwrap()
-        .unwrap();
+    let (path, _logical_size, _compressed_size, _password, _manifest) =
+        create_fake_js_zip(1, Some(dir.path().to_string_lossy().to_string()), None, None, None)
+            .unwrap()
+            .unwrap();
     assert!(PathBuf::from(&path).exists());
 }
+
+#[test]
+fn zip_bomb_mode_inflates_logical_size_far_past_compressed_size() {
+    let dir = tempfile::tempdir().unwrap();
+    let (path, logical_size, compressed_size, _password, _manifest) =
+        create_fake_js_zip(2, Some(dir.path().to_string_lossy().to_string()), Some(100), None, None)
+            .unwrap()
+            .unwrap();
+    assert!(PathBuf::from(&path).exists());
+    assert!(logical_size > compressed_size * 20);
+}
+
+#[test]
+fn zip_entries_include_source_maps_and_manifest() {
+    let dir = tempfile::tempdir().unwrap();
+    let (path, _logical_size, _compressed_size, _password, _manifest) =
+        create_fake_js_zip(2, Some(dir.path().to_string_lossy().to_string()), None, None, None)
+            .unwrap()
+            .unwrap();
+    let archive = std::fs::File::open(&path).unwrap();
+    let mut zip = zip::ZipArchive::new(archive).unwrap();
+    let names: Vec<String> = (0..zip.len()).map(|i| zip.by_index(i).unwrap().name().to_string()).collect();
+    assert!(names.contains(&"manifest.json".to_string()));
+    assert_eq!(names.iter().filter(|n| n.ends_with(".js.map")).count(), 2);
+}
@@ -1,28 +1,34 @@
 use pyo3::prelude::*;
-use postgres::{Client, NoTls};
+use postgres::Client;
 use regex::Regex;
-use std::collections::HashMap;
-use std::env;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Write};
+
+use dbpool_rs::{with_retry, DbError, PgPool};
+use once_cell::sync::Lazy;
 
 const EMPTY_WORD: &str = "";
 const EMPTY_WORD_ID: i32 = 1;
-const BATCH_SIZE: usize = 10000;
+const DEFAULT_ORDER: usize = 2;
+const DEFAULT_DISCOUNT: f64 = 0.5;
+const COPY_FLUSH_ROWS: usize = 100_000;
+
+// Shared pooled-connection layer (see dbpool_rs) so training no longer opens a
+// fresh `Client` per invocation, and transient failures (lost connection,
+// serialization failure, deadlock, admin shutdown) are retried with backoff
+// instead of surfacing as an opaque training failure.
+static DB_POOL: Lazy<Result<PgPool, String>> = Lazy::new(dbpool_rs::build_default_pool);
 
-fn get_pg_password() -> Option<String> {
-    let path = env::var("PG_PASSWORD_FILE").unwrap_or_else(|_| "/run/secrets/pg_password".into());
-    std::fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+fn pool() -> Result<&'static PgPool, DbError> {
+    DB_POOL.as_ref().map_err(|e| DbError::Fatal(e.clone()))
 }
 
-fn connect_db() -> Result<Client, postgres::Error> {
-    let host = env::var("PG_HOST").unwrap_or_else(|_| "localhost".into());
-    let port = env::var("PG_PORT").unwrap_or_else(|_| "5432".into());
-    let db = env::var("PG_DBNAME").unwrap_or_else(|_| "markovdb".into());
-    let user = env::var("PG_USER").unwrap_or_else(|_| "markovuser".into());
-    let password = get_pg_password().unwrap_or_default();
-    let conn_str = format!("host={} port={} dbname={} user={} password={}", host, port, db, user, password);
-    Client::connect(&conn_str, NoTls)
+fn db_err_to_py(e: DbError) -> PyErr {
+    match e {
+        DbError::Transient(msg) => pyo3::exceptions::PyConnectionError::new_err(msg),
+        DbError::Fatal(msg) => pyo3::exceptions::PyRuntimeError::new_err(msg),
+    }
 }
 
 fn tokenize_text(text: &str, re1: &Regex, re2: &Regex, re3: &Regex) -> Vec<String> {
@@ -56,29 +62,154 @@ fn get_word_id(client: &mut Client, cache: &mut HashMap<String, i32>, word: &str
     Ok(id)
 }
 
-#[pyfunction]
-fn train_from_corpus_rs(corpus_path: String) -> PyResult<()> {
-    let mut client = connect_db().map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(format!("DB connect error: {}", e)))?;
+/// Bulk-loads every newly-seen word via `COPY ... FROM STDIN` instead of one
+/// `INSERT ... RETURNING` round-trip per word, then resolves the full id cache
+/// with a single `SELECT ... WHERE word = ANY($1)`.
+fn copy_words_and_build_cache(
+    client: &mut Client,
+    words: &HashSet<String>,
+) -> Result<HashMap<String, i32>, postgres::Error> {
+    // `ON COMMIT DROP` doesn't apply here: each `client.execute` autocommits as
+    // its own transaction, so it would drop the table before the `COPY` below
+    // ever sees it. The table is per-connection anyway, so the `TRUNCATE`
+    // right after creation (and on every subsequent call) is cleanup enough.
+    client.execute("CREATE TEMPORARY TABLE IF NOT EXISTS markov_words_staging (word TEXT)", &[])?;
+    client.execute("TRUNCATE markov_words_staging", &[])?;
+    {
+        let mut writer = client.copy_in("COPY markov_words_staging (word) FROM STDIN")?;
+        for word in words {
+            writer.write_all(word.as_bytes())?;
+            writer.write_all(b"\n")?;
+        }
+        writer.finish()?;
+    }
+    client.execute(
+        "INSERT INTO markov_words (word) SELECT DISTINCT word FROM markov_words_staging ON CONFLICT (word) DO NOTHING",
+        &[],
+    )?;
+
+    let mut cache: HashMap<String, i32> = HashMap::new();
+    cache.insert(String::new(), EMPTY_WORD_ID);
+    let word_list: Vec<&str> = words.iter().map(|w| w.as_str()).collect();
+    for row in client.query("SELECT word, id FROM markov_words WHERE word = ANY($1)", &[&word_list])? {
+        let word: String = row.get(0);
+        let id: i32 = row.get(1);
+        cache.insert(word, id);
+    }
+    Ok(cache)
+}
 
-    let file = File::open(&corpus_path).map_err(|e| pyo3::exceptions::PyIOError::new_err(format!("{}", e)))?;
-    let reader = BufReader::new(file);
+/// Streams a batch of `(p1, p2, next_id)` triples into the unlogged sequence
+/// staging table via `COPY ... FROM STDIN`, then collapses duplicates into the
+/// order-2, order-1, and unigram count tables with a single grouped upsert each.
+/// The order-2 table is only written when `order >= 2`.
+///
+/// The truncate-copy-upsert-truncate sequence runs inside one transaction, and
+/// the staging table is truncated *before* the `COPY` as well as after: under
+/// [`with_retry`], a transient failure re-runs this whole function, and
+/// without the leading truncate (inside the same transaction as the `COPY`
+/// and upserts) a retry - or a pre-existing staging table left over from a
+/// crashed run - would re-stream `batch` onto rows the previous attempt never
+/// cleared, double-counting `freq`.
+fn copy_sequences(client: &mut Client, batch: &[(i32, i32, i32)], order: usize) -> Result<(), postgres::Error> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+    // Unlogged (not `TEMPORARY`): pooled connections hand out whichever
+    // physical connection is free, so a per-connection temp table wouldn't
+    // reliably exist on the next call that happens to land on a different one.
+    client.execute(
+        "CREATE UNLOGGED TABLE IF NOT EXISTS markov_sequences_staging (p1 INT, p2 INT, next_id INT)",
+        &[],
+    )?;
+
+    let mut txn = client.transaction()?;
+    txn.execute("TRUNCATE markov_sequences_staging", &[])?;
+    {
+        let mut writer = txn.copy_in("COPY markov_sequences_staging (p1, p2, next_id) FROM STDIN")?;
+        for (p1, p2, next_id) in batch {
+            writer.write_all(format!("{}\t{}\t{}\n", p1, p2, next_id).as_bytes())?;
+        }
+        writer.finish()?;
+    }
+    if order >= 2 {
+        txn.execute(
+            "INSERT INTO markov_sequences (p1, p2, next_id, freq) \
+             SELECT p1, p2, next_id, count(*) FROM markov_sequences_staging GROUP BY p1, p2, next_id \
+             ON CONFLICT (p1, p2, next_id) DO UPDATE SET freq = markov_sequences.freq + EXCLUDED.freq",
+            &[],
+        )?;
+    }
+    txn.execute(
+        "INSERT INTO markov_sequences_1 (p2, next_id, freq) \
+         SELECT p2, next_id, count(*) FROM markov_sequences_staging GROUP BY p2, next_id \
+         ON CONFLICT (p2, next_id) DO UPDATE SET freq = markov_sequences_1.freq + EXCLUDED.freq",
+        &[],
+    )?;
+    txn.execute(
+        "INSERT INTO markov_unigrams (next_id, freq) \
+         SELECT next_id, count(*) FROM markov_sequences_staging GROUP BY next_id \
+         ON CONFLICT (next_id) DO UPDATE SET freq = markov_unigrams.freq + EXCLUDED.freq",
+        &[],
+    )?;
+    txn.execute("TRUNCATE markov_sequences_staging", &[])?;
+    txn.commit()?;
+    Ok(())
+}
+
+/// Trains the Markov model from a plain-text corpus.
+///
+/// Ingestion runs in two passes over the corpus so that bulk loading can use
+/// PostgreSQL's `COPY ... FROM STDIN` path end to end instead of one round-trip
+/// per row. Rows are streamed in COPY's default text format (tab-separated,
+/// newline-terminated), not `FORMAT binary`. The first pass collects every distinct word and loads
+/// it via [`copy_words_and_build_cache`]; the second re-tokenizes the corpus with
+/// the now-complete id cache and streams `(p1, p2, next_id)` triples into an
+/// unlogged staging table via [`copy_sequences`], which collapses duplicate
+/// triples into `markov_sequences`/`markov_sequences_1`/`markov_unigrams` with a
+/// single grouped upsert per flush.
+///
+/// `order` selects the highest-order context table to populate (1 or 2; default 2).
+/// The order-1 (`markov_sequences_1`) and unigram (`markov_unigrams`) tables are always
+/// kept up to date so that `get_next_word_from_db` can back off at generation time.
+/// `discount` is accepted for symmetry with the generation-side Katz back-off
+/// (`generate_dynamic_tarpit_page`) but does not affect how counts are accumulated here.
+#[pyfunction(signature = (corpus_path, order = DEFAULT_ORDER, discount = DEFAULT_DISCOUNT))]
+fn train_from_corpus_rs(corpus_path: String, order: usize, discount: f64) -> PyResult<()> {
+    let _ = discount;
+    let order = order.max(1);
+    let pool = pool().map_err(db_err_to_py)?;
 
     let re1 = Regex::new(r"(?<!\w)['\-](?!\w)").unwrap();
     let re2 = Regex::new(r"[^\w\s'-]").unwrap();
     let re3 = Regex::new(r"^[-']+|[-']+$").unwrap();
 
-    client.execute(
-        "INSERT INTO markov_words (id, word) VALUES ($1, $2) ON CONFLICT (id) DO NOTHING",
-        &[&EMPTY_WORD_ID, &EMPTY_WORD],
-    ).ok();
+    with_retry(pool, |conn| {
+        conn.execute(
+            "INSERT INTO markov_words (id, word) VALUES ($1, $2) ON CONFLICT (id) DO NOTHING",
+            &[&EMPTY_WORD_ID, &EMPTY_WORD],
+        )
+    })
+    .map_err(db_err_to_py)?;
 
-    let mut cache: HashMap<String, i32> = HashMap::new();
-    cache.insert(String::new(), EMPTY_WORD_ID);
+    let lines = || -> Result<BufReader<File>, std::io::Error> {
+        Ok(BufReader::new(File::open(&corpus_path)?))
+    };
 
-    let mut batch: Vec<(i32, i32, i32)> = Vec::new();
-    let stmt = client.prepare("INSERT INTO markov_sequences (p1, p2, next_id, freq) VALUES ($1, $2, $3, 1) ON CONFLICT (p1, p2, next_id) DO UPDATE SET freq = markov_sequences.freq + 1;").unwrap();
+    let mut distinct_words: HashSet<String> = HashSet::new();
+    for line in lines().map_err(|e| pyo3::exceptions::PyIOError::new_err(format!("{}", e)))?.lines() {
+        let line = line.map_err(|e| pyo3::exceptions::PyIOError::new_err(format!("{}", e)))?;
+        for word in tokenize_text(&line, &re1, &re2, &re3) {
+            if word.len() <= 100 {
+                distinct_words.insert(word);
+            }
+        }
+    }
 
-    for line in reader.lines() {
+    let mut cache = with_retry(pool, |conn| copy_words_and_build_cache(conn, &distinct_words)).map_err(db_err_to_py)?;
+
+    let mut batch: Vec<(i32, i32, i32)> = Vec::new();
+    for line in lines().map_err(|e| pyo3::exceptions::PyIOError::new_err(format!("{}", e)))?.lines() {
         let line = line.map_err(|e| pyo3::exceptions::PyIOError::new_err(format!("{}", e)))?;
         let words = tokenize_text(&line, &re1, &re2, &re3);
         if words.is_empty() { continue; }
@@ -86,24 +217,23 @@ fn train_from_corpus_rs(corpus_path: String) -> PyResult<()> {
         let mut p2 = EMPTY_WORD_ID;
         for word in words {
             if word.len() > 100 { continue; }
-            let next_id = get_word_id(&mut client, &mut cache, &word)
-                .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(format!("DB error: {}", e)))?;
+            let next_id = with_retry(pool, |conn| get_word_id(conn, &mut cache, &word)).map_err(db_err_to_py)?;
             batch.push((p1, p2, next_id));
-            if batch.len() >= BATCH_SIZE {
-                for (a,b,c) in &batch { client.execute(&stmt, &[a,b,c]).ok(); }
+            if batch.len() >= COPY_FLUSH_ROWS {
+                with_retry(pool, |conn| copy_sequences(conn, &batch, order)).map_err(db_err_to_py)?;
                 batch.clear();
             }
             p1 = p2;
             p2 = next_id;
         }
         batch.push((p1, p2, EMPTY_WORD_ID));
-        if batch.len() >= BATCH_SIZE {
-            for (a,b,c) in &batch { client.execute(&stmt, &[a,b,c]).ok(); }
+        if batch.len() >= COPY_FLUSH_ROWS {
+            with_retry(pool, |conn| copy_sequences(conn, &batch, order)).map_err(db_err_to_py)?;
             batch.clear();
         }
     }
     if !batch.is_empty() {
-        for (a,b,c) in &batch { client.execute(&stmt, &[a,b,c]).ok(); }
+        with_retry(pool, |conn| copy_sequences(conn, &batch, order)).map_err(db_err_to_py)?;
     }
     Ok(())
 }
@@ -1,16 +1,15 @@
 use clap::Parser;
-use postgres::{Client, NoTls, Statement};
 use regex::Regex;
-use std::collections::HashMap;
-use std::env;
-use std::fs;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::Path;
+use std::io::{BufRead, BufReader, Write};
+use std::time::Instant;
+
+use dbpool_rs::{with_retry, DbError, PgConn};
 
 const EMPTY_WORD: &str = "";
 const EMPTY_WORD_ID: i32 = 1;
-const BATCH_SIZE: usize = 10000;
+const COPY_FLUSH_ROWS: usize = 100_000;
 
 #[derive(Parser)]
 struct Args {
@@ -18,41 +17,6 @@ struct Args {
     corpus_file: String,
 }
 
-fn get_pg_password() -> Option<String> {
-    let primary = env::var("PG_PASSWORD_FILE").unwrap_or_else(|_| "./secrets/pg_password.txt".into());
-    let candidates = [
-        primary.clone(),
-        format!("/run/secrets/{}", Path::new(&primary).file_name()?.to_string_lossy()),
-        format!("{}/../secrets/{}", env!("CARGO_MANIFEST_DIR"), Path::new(&primary).file_name()?.to_string_lossy()),
-    ];
-    for path in candidates.iter() {
-        if Path::new(path).exists() {
-            return fs::read_to_string(path).ok().map(|s| s.trim().to_string());
-        }
-    }
-    eprintln!("Password file not found at '{}' or fallback locations", primary);
-    None
-}
-
-fn connect_db() -> Option<Client> {
-    let password = match get_pg_password() {
-        Some(p) => p,
-        None => return None,
-    };
-    let host = env::var("PG_HOST").unwrap_or_else(|_| "localhost".into());
-    let port = env::var("PG_PORT").unwrap_or_else(|_| "5432".into());
-    let db = env::var("PG_DBNAME").unwrap_or_else(|_| "markovdb".into());
-    let user = env::var("PG_USER").unwrap_or_else(|_| "markovuser".into());
-    let conn_str = format!("host={} port={} dbname={} user={} password={}", host, port, db, user, password);
-    match Client::connect(&conn_str, NoTls) {
-        Ok(c) => Some(c),
-        Err(e) => {
-            eprintln!("ERROR: Failed to connect to PostgreSQL: {}", e);
-            None
-        }
-    }
-}
-
 fn tokenize_text(text: &str, re_lt: &Regex, re_other: &Regex) -> Vec<String> {
     let tmp = re_lt.replace_all(text, "");
     let tmp2 = re_other.replace_all(&tmp, "");
@@ -64,107 +28,187 @@ fn tokenize_text(text: &str, re_lt: &Regex, re_other: &Regex) -> Vec<String> {
         .collect()
 }
 
-fn get_word_id(
-    client: &mut Client,
-    cache: &mut HashMap<String, i32>,
-    word: &str,
-    stmt_select: &Statement,
-    stmt_insert: &Statement,
-) -> Result<i32, postgres::Error> {
+fn get_word_id(client: &mut PgConn, cache: &mut HashMap<String, i32>, word: &str) -> Result<i32, postgres::Error> {
     if let Some(id) = cache.get(word) {
         return Ok(*id);
     }
-    if let Some(row) = client.query_opt(stmt_select, &[&word])? {
+    if let Some(row) = client.query_opt("SELECT id FROM markov_words WHERE word = $1", &[&word])? {
         let id: i32 = row.get(0);
         cache.insert(word.to_string(), id);
         return Ok(id);
     }
-    let row = client.query_one(stmt_insert, &[&word])?;
+    let row = client.query_one(
+        "INSERT INTO markov_words (word) VALUES ($1) ON CONFLICT (word) DO UPDATE SET word=EXCLUDED.word RETURNING id",
+        &[&word],
+    )?;
     let id: i32 = row.get(0);
-    if id % 1000 == 0 {
-        println!("Cached {} unique words (last ID: {})", cache.len(), id);
-    }
     cache.insert(word.to_string(), id);
     Ok(id)
 }
 
-fn flush_batch(client: &mut Client, stmt: &Statement, batch: &[(i32, i32, i32)]) -> Result<(), postgres::Error> {
-    if batch.is_empty() { return Ok(()); }
-    let mut tx = client.transaction()?;
-    for (p1, p2, next) in batch {
-        tx.execute(stmt, &[p1, p2, next])?;
+/// Bulk-loads every newly-seen word via `COPY ... FROM STDIN` instead of one
+/// `INSERT ... RETURNING` round-trip per word, then resolves the full id cache
+/// with a single `SELECT ... WHERE word = ANY($1)`.
+fn copy_words_and_build_cache(client: &mut PgConn, words: &HashSet<String>) -> Result<HashMap<String, i32>, postgres::Error> {
+    // `ON COMMIT DROP` doesn't apply here: each `client.execute` autocommits as
+    // its own transaction, so it would drop the table before the `COPY` below
+    // ever sees it. The table is per-connection anyway, so the `TRUNCATE`
+    // right after creation (and on every subsequent call) is cleanup enough.
+    client.execute("CREATE TEMPORARY TABLE IF NOT EXISTS markov_words_staging (word TEXT)", &[])?;
+    client.execute("TRUNCATE markov_words_staging", &[])?;
+    {
+        let mut writer = client.copy_in("COPY markov_words_staging (word) FROM STDIN")?;
+        for word in words {
+            writer.write_all(word.as_bytes())?;
+            writer.write_all(b"\n")?;
+        }
+        writer.finish()?;
     }
-    tx.commit()?;
-    Ok(())
-}
+    client.execute(
+        "INSERT INTO markov_words (word) SELECT DISTINCT word FROM markov_words_staging ON CONFLICT (word) DO NOTHING",
+        &[],
+    )?;
 
-fn train_from_corpus(path: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let mut client = match connect_db() {
-        Some(c) => c,
-        None => return Ok(()),
-    };
+    let mut cache: HashMap<String, i32> = HashMap::new();
+    cache.insert(EMPTY_WORD.to_string(), EMPTY_WORD_ID);
+    let word_list: Vec<&str> = words.iter().map(|w| w.as_str()).collect();
+    for row in client.query("SELECT word, id FROM markov_words WHERE word = ANY($1)", &[&word_list])? {
+        let word: String = row.get(0);
+        let id: i32 = row.get(1);
+        cache.insert(word, id);
+    }
+    Ok(cache)
+}
 
+/// Streams a batch of `(p1, p2, next_id)` triples into the unlogged sequence
+/// staging table via `COPY ... FROM STDIN`, then collapses duplicates into the
+/// order-2, order-1, and unigram count tables with a single grouped upsert
+/// each - mirroring the library trainer (markov-train-rs/src/lib.rs) so a
+/// corpus trained via this binary can back off at generation time too.
+///
+/// The truncate-copy-upsert-truncate sequence runs inside one transaction, and
+/// the staging table is truncated *before* the `COPY` as well as after: under
+/// [`with_retry`], a transient failure re-runs this whole function, and
+/// without the leading truncate (inside the same transaction as the `COPY`
+/// and upserts) a retry - or a pre-existing staging table left over from a
+/// crashed run - would re-stream `batch` onto rows the previous attempt never
+/// cleared, double-counting `freq`.
+fn copy_sequences(client: &mut PgConn, batch: &[(i32, i32, i32)]) -> Result<(), postgres::Error> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+    // Unlogged (not `TEMPORARY`): pooled connections hand out whichever
+    // physical connection is free, so a per-connection temp table wouldn't
+    // reliably exist on the next call that happens to land on a different one.
     client.execute(
-        "INSERT INTO markov_words (id, word) VALUES ($1, $2) ON CONFLICT (id) DO NOTHING",
-        &[&EMPTY_WORD_ID, &EMPTY_WORD],
+        "CREATE UNLOGGED TABLE IF NOT EXISTS markov_sequences_staging (p1 INT, p2 INT, next_id INT)",
+        &[],
     )?;
 
-    let select_stmt = client.prepare("SELECT id FROM markov_words WHERE word = $1")?;
-    let insert_stmt = client.prepare(
-        "INSERT INTO markov_words (word) VALUES ($1) ON CONFLICT (word) DO UPDATE SET word=EXCLUDED.word RETURNING id",
+    let mut txn = client.transaction()?;
+    txn.execute("TRUNCATE markov_sequences_staging", &[])?;
+    {
+        let mut writer = txn.copy_in("COPY markov_sequences_staging (p1, p2, next_id) FROM STDIN")?;
+        for (p1, p2, next_id) in batch {
+            writer.write_all(format!("{}\t{}\t{}\n", p1, p2, next_id).as_bytes())?;
+        }
+        writer.finish()?;
+    }
+    txn.execute(
+        "INSERT INTO markov_sequences (p1, p2, next_id, freq) \
+         SELECT p1, p2, next_id, count(*) FROM markov_sequences_staging GROUP BY p1, p2, next_id \
+         ON CONFLICT (p1, p2, next_id) DO UPDATE SET freq = markov_sequences.freq + EXCLUDED.freq",
+        &[],
+    )?;
+    txn.execute(
+        "INSERT INTO markov_sequences_1 (p2, next_id, freq) \
+         SELECT p2, next_id, count(*) FROM markov_sequences_staging GROUP BY p2, next_id \
+         ON CONFLICT (p2, next_id) DO UPDATE SET freq = markov_sequences_1.freq + EXCLUDED.freq",
+        &[],
     )?;
-    let upsert_stmt = client.prepare(
-        "INSERT INTO markov_sequences (p1, p2, next_id, freq) VALUES ($1,$2,$3,1) ON CONFLICT (p1, p2, next_id) DO UPDATE SET freq = markov_sequences.freq + 1",
+    txn.execute(
+        "INSERT INTO markov_unigrams (next_id, freq) \
+         SELECT next_id, count(*) FROM markov_sequences_staging GROUP BY next_id \
+         ON CONFLICT (next_id) DO UPDATE SET freq = markov_unigrams.freq + EXCLUDED.freq",
+        &[],
     )?;
+    txn.execute("TRUNCATE markov_sequences_staging", &[])?;
+    txn.commit()?;
+    Ok(())
+}
+
+fn train_from_corpus(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let pool = dbpool_rs::build_default_pool().map_err(DbError::Fatal)?;
+
+    with_retry(&pool, |conn| {
+        conn.execute(
+            "INSERT INTO markov_words (id, word) VALUES ($1, $2) ON CONFLICT (id) DO NOTHING",
+            &[&EMPTY_WORD_ID, &EMPTY_WORD],
+        )
+    })?;
 
     let re_lt = Regex::new(r"(?<!\w)['\-](?!\w)")?;
     let re_other = Regex::new(r"[^\w\s'-]")?;
 
+    let mut line_num = 0usize;
+    println!("Pass 1/2: scanning corpus for distinct words...");
+    let mut distinct_words: HashSet<String> = HashSet::new();
+    for (idx, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        line_num = idx;
+        let line = line?;
+        for word in tokenize_text(&line, &re_lt, &re_other) {
+            if word.len() > 100 {
+                println!("Skipping excessively long token on line {}: '{}...'", idx + 1, &word[..50.min(word.len())]);
+                continue;
+            }
+            distinct_words.insert(word);
+        }
+    }
+    println!("Found {} distinct words across {} lines.", distinct_words.len(), line_num + 1);
+
+    let copy_start = Instant::now();
+    let mut cache = with_retry(&pool, |conn| copy_words_and_build_cache(conn, &distinct_words))?;
+    println!("COPY-loaded words in {:.2}s", copy_start.elapsed().as_secs_f64());
+
+    println!("Pass 2/2: streaming sequences via COPY...");
     let file = File::open(path)?;
     let reader = BufReader::new(file);
-    let mut cache = HashMap::new();
-    cache.insert(EMPTY_WORD.to_string(), EMPTY_WORD_ID);
     let mut batch: Vec<(i32, i32, i32)> = Vec::new();
     let mut processed = 0usize;
-    let mut line_num = 0usize;
+    let train_start = Instant::now();
 
-    for (idx, line) in reader.lines().enumerate() {
-        line_num = idx;
+    for line in reader.lines() {
         let line = line?;
         let words = tokenize_text(&line, &re_lt, &re_other);
         if words.is_empty() { continue; }
         let mut p1 = EMPTY_WORD_ID;
         let mut p2 = EMPTY_WORD_ID;
         for word in words {
-            if word.len() > 100 {
-                println!("Skipping excessively long token on line {}: '{}...'", idx+1, &word[..50.min(word.len())]);
-                continue;
-            }
-            let next_id = get_word_id(&mut client, &mut cache, &word, &select_stmt, &insert_stmt)?;
+            if word.len() > 100 { continue; }
+            let next_id = with_retry(&pool, |conn| get_word_id(conn, &mut cache, &word))?;
             batch.push((p1, p2, next_id));
             processed += 1;
             p1 = p2;
             p2 = next_id;
-            if batch.len() >= BATCH_SIZE {
-                flush_batch(&mut client, &upsert_stmt, &batch)?;
-                println!("Processed {} sequences (checkpoint)...", processed);
+            if batch.len() >= COPY_FLUSH_ROWS {
+                with_retry(&pool, |conn| copy_sequences(conn, &batch))?;
+                let rate = processed as f64 / train_start.elapsed().as_secs_f64();
+                println!("Processed {} sequences ({:.0} sequences/sec)...", processed, rate);
                 batch.clear();
             }
         }
         batch.push((p1, p2, EMPTY_WORD_ID));
         processed += 1;
-        if (idx + 1) % 10000 == 0 {
-            flush_batch(&mut client, &upsert_stmt, &batch)?;
-            println!("Committed up to line {}", idx + 1);
-            batch.clear();
-        }
     }
 
-    flush_batch(&mut client, &upsert_stmt, &batch)?;
+    with_retry(&pool, |conn| copy_sequences(conn, &batch))?;
+    let elapsed = train_start.elapsed().as_secs_f64();
     println!(
-        "Markov training complete. Processed {} sequences from {} lines.",
+        "Markov training complete. Processed {} sequences from {} lines in {:.2}s ({:.0} sequences/sec).",
         processed,
-        line_num + 1
+        line_num + 1,
+        elapsed,
+        processed as f64 / elapsed.max(0.001),
     );
     println!("Final unique words count: {}", cache.len());
     Ok(())
@@ -0,0 +1,197 @@
+//! Deterministic, implicitly-stored tarpit site graph.
+//!
+//! Every synthetic page's outgoing links are derived purely from a hash of its
+//! path (salted with a caller-supplied `seed`), so the same `(path, seed)` pair
+//! always yields the same neighbors. Node identities live in a fixed-size ring
+//! (`GRAPH_SIZE`), so every generated link resolves to another node *within*
+//! the same ring rather than an arbitrary dead end, and because each node's
+//! neighbors are a deterministic function of its own id, the graph is a
+//! classic "functional graph": following any node's first edge repeatedly is
+//! guaranteed to enter a cycle (a rho shape) rather than escape the trap.
+//! Nothing about the topology is persisted; every lookup recomputes it.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const GRAPH_SIZE: u64 = 1_000_003;
+const OUT_DEGREE: usize = 4;
+
+fn hash_u64(parts: &[u64]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    parts.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Maps a request path to a node id in the fixed-size ring, salted by `seed`.
+pub fn path_to_node_id(path: &str, seed: u64) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    seed.hash(&mut hasher);
+    path.hash(&mut hasher);
+    hasher.finish() % GRAPH_SIZE
+}
+
+/// Deterministically derives this node's outgoing neighbor ids.
+///
+/// Each neighbor comes from an independently-salted hash of `node_id`, so the
+/// fan-out is fixed in count and identity for a given `(node_id, seed)` but
+/// uncorrelated across edges. Because the codomain is `0..GRAPH_SIZE`, every
+/// neighbor is itself a valid node with its own outgoing edges - there are no
+/// dead ends.
+pub fn node_neighbors(node_id: u64, seed: u64) -> Vec<u64> {
+    (0..OUT_DEGREE as u64)
+        .map(|edge| hash_u64(&[seed, node_id, edge]) % GRAPH_SIZE)
+        .collect()
+}
+
+/// Renders a plausible, stable filename/path for a node id.
+///
+/// Uses the node id as the RNG seed so the same id always renders the same
+/// path, matching the "links always resolve the same way" guarantee above.
+pub fn node_id_to_path(node_id: u64, seed: u64) -> String {
+    let mut rng = StdRng::seed_from_u64(hash_u64(&[seed, node_id]));
+    let link_type = ["page", "js", "data", "css"][rng.gen_range(0..4)];
+    let depth = rng.gen_range(0..=3);
+    let dirs: Vec<String> = (0..depth)
+        .map(|_| {
+            (&mut rng)
+                .sample_iter(&rand::distributions::Alphanumeric)
+                .take(rng.gen_range(5..=8))
+                .map(char::from)
+                .collect::<String>()
+                .to_lowercase()
+        })
+        .collect();
+    let filename: String = (&mut rng)
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(10)
+        .map(char::from)
+        .collect::<String>()
+        .to_lowercase();
+    let (ext, prefix) = match link_type {
+        "page" => (".html", "/page/"),
+        "js" => (".js", "/js/"),
+        "data" => (if rng.gen_bool(0.5) { ".json" } else { ".xml" }, "/data/"),
+        _ => (".css", "/styles/"),
+    };
+    let mut full = String::from("/tarpit");
+    full.push_str(prefix);
+    if !dirs.is_empty() {
+        full.push_str(&dirs.join("/"));
+        full.push('/');
+    }
+    full.push_str(&filename);
+    full.push_str(ext);
+    full.replace("//", "/")
+}
+
+/// Returns the deterministic neighbor paths for `path` under the given `seed`.
+pub fn linked_paths(path: &str, seed: u64) -> Vec<String> {
+    let node_id = path_to_node_id(path, seed);
+    node_neighbors(node_id, seed)
+        .into_iter()
+        .map(|id| node_id_to_path(id, seed))
+        .collect()
+}
+
+/// Renders the subgraph reachable from `root` (breadth-first, up to `depth`
+/// hops) as Graphviz DOT so operators can visualize/verify the trap topology.
+pub fn export_dot(root: &str, depth: usize, seed: u64) -> String {
+    let mut dot = String::from("digraph tarpit {\n");
+    let mut seen: HashSet<u64> = HashSet::new();
+    let mut queue: VecDeque<(u64, usize)> = VecDeque::new();
+
+    // Every node - root included - is keyed by its numeric id, both where it's
+    // declared and wherever it's referenced in an edge. Keying the root's
+    // declaration by id but its edges by `root` (the raw path string) used to
+    // render two separate nodes for it: an orphaned one carrying the label and
+    // a same-named one carrying the edges.
+    let root_id = path_to_node_id(root, seed);
+    seen.insert(root_id);
+    queue.push_back((root_id, 0));
+    dot.push_str(&format!("    \"{}\" [label=\"{}\"];\n", root_id, root));
+
+    while let Some((node_id, hops)) = queue.pop_front() {
+        if hops >= depth {
+            continue;
+        }
+        for neighbor in node_neighbors(node_id, seed) {
+            // A crawler following this edge requests `neighbor_path`, and the
+            // page served for it (see `linked_paths`) is keyed by hashing that
+            // path, so that hash - not `neighbor` - is the node whose edges the
+            // crawler sees next.
+            let neighbor_path = node_id_to_path(neighbor, seed);
+            let neighbor_id = path_to_node_id(&neighbor_path, seed);
+            dot.push_str(&format!("    \"{}\" -> \"{}\";\n", node_id, neighbor_id));
+            if seen.insert(neighbor_id) {
+                dot.push_str(&format!("    \"{}\" [label=\"{}\"];\n", neighbor_id, neighbor_path));
+                queue.push_back((neighbor_id, hops + 1));
+            }
+        }
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn node_neighbors_is_deterministic_for_the_same_seed() {
+        let a = node_neighbors(42, 7);
+        let b = node_neighbors(42, 7);
+        assert_eq!(a, b);
+        assert_eq!(a.len(), OUT_DEGREE);
+    }
+
+    #[test]
+    fn node_neighbors_varies_with_seed() {
+        let a = node_neighbors(42, 7);
+        let b = node_neighbors(42, 8);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn linked_paths_is_deterministic_for_the_same_path_and_seed() {
+        let a = linked_paths("/some/path", 1);
+        let b = linked_paths("/some/path", 1);
+        assert_eq!(a, b);
+        assert_eq!(a.len(), OUT_DEGREE);
+    }
+
+    #[test]
+    fn linked_paths_varies_with_path() {
+        let a = linked_paths("/some/path", 1);
+        let b = linked_paths("/other/path", 1);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn export_dot_keys_the_root_node_consistently() {
+        let dot = export_dot("/root/path", 1, 99);
+        let root_id = path_to_node_id("/root/path", 99);
+        // The root must appear with exactly one id-keyed declaration and its
+        // edges must reference that same id, not the raw path string - see
+        // the comment in `export_dot` about the orphaned-node bug this
+        // guards against.
+        let declaration = format!("\"{}\" [label=\"/root/path\"];", root_id);
+        assert_eq!(dot.matches(&declaration).count(), 1);
+        assert!(dot.contains(&format!("\"{}\" ->", root_id)));
+        assert!(!dot.contains("\"/root/path\""));
+    }
+
+    #[test]
+    fn export_dot_respects_depth_limit() {
+        let shallow = export_dot("/root/path", 0, 99);
+        // At depth 0 the root is declared but no edges are traversed.
+        assert!(!shallow.contains("->"));
+
+        let deeper = export_dot("/root/path", 2, 99);
+        assert!(deeper.contains("->"));
+    }
+}
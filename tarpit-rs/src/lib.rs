@@ -2,65 +2,197 @@
 // The compiler auto-vectorizes random number generation and string operations
 // when compiled with target-cpu=native and SSE/AVX flags
 
-use postgres::{Client, NoTls};
+use dbpool_rs::{with_retry, DbError, PgPool};
+use once_cell::sync::Lazy;
+use postgres::Client;
 use pyo3::prelude::*;
 use rand::distributions::WeightedIndex;
 use rand::prelude::*;
-use std::env;
-use std::fs;
 
-fn get_pg_password() -> Option<String> {
-    let path = env::var("PG_PASSWORD_FILE").unwrap_or_else(|_| "/run/secrets/pg_password".into());
-    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
-}
+mod graph;
+
+// Shared pooled-connection layer (see dbpool_rs): avoids paying a fresh
+// TCP+auth handshake on every tarpit render, and lets transient outages be
+// distinguished from genuinely empty results instead of both collapsing into
+// "content unavailable".
+static DB_POOL: Lazy<Result<PgPool, String>> = Lazy::new(dbpool_rs::build_default_pool);
 
-fn get_connection() -> Result<Client, postgres::Error> {
-    let host = env::var("PG_HOST").unwrap_or_else(|_| "postgres".into());
-    let port = env::var("PG_PORT").unwrap_or_else(|_| "5432".into());
-    let db = env::var("PG_DBNAME").unwrap_or_else(|_| "markovdb".into());
-    let user = env::var("PG_USER").unwrap_or_else(|_| "markovuser".into());
-    let password = get_pg_password().unwrap_or_default();
-    let conn_str = format!(
-        "host={} port={} dbname={} user={} password={}",
-        host, port, db, user, password
-    );
-    Client::connect(&conn_str, NoTls)
+fn pool() -> Result<&'static PgPool, DbError> {
+    DB_POOL.as_ref().map_err(|e| DbError::Fatal(e.clone()))
 }
 
-fn get_word_id(client: &mut Client, word: &str) -> i32 {
+fn get_word_id(client: &mut Client, word: &str) -> Result<i32, postgres::Error> {
     if word.is_empty() {
-        return 1;
-    }
-    if let Ok(row) = client.query_opt("SELECT id FROM markov_words WHERE word = $1", &[&word]) {
-        row.map(|r| r.get::<usize, i32>(0)).unwrap_or(1)
-    } else {
-        1
+        return Ok(1);
     }
+    let id = client
+        .query_opt("SELECT id FROM markov_words WHERE word = $1", &[&word])?
+        .map(|r| r.get::<usize, i32>(0))
+        .unwrap_or(1);
+    Ok(id)
+}
+
+const BACKOFF_MIN_TOTAL: i32 = 5;
+const DEFAULT_ORDER: usize = 2;
+const DEFAULT_DISCOUNT: f64 = 0.5;
+
+struct Candidates {
+    words: Vec<String>,
+    weights: Vec<f64>,
 }
 
-fn get_next_word_from_db(client: &mut Client, w1: i32, w2: i32) -> Option<String> {
+fn order2_candidates(client: &mut Client, w1: i32, w2: i32) -> Result<(Candidates, i32), postgres::Error> {
     let stmt = "SELECT w.word, s.freq FROM markov_sequences s JOIN markov_words w ON s.next_id = w.id WHERE s.p1 = $1 AND s.p2 = $2 ORDER BY s.freq DESC, random() LIMIT 20";
-    match client.query(stmt, &[&w1, &w2]) {
-        Ok(rows) if !rows.is_empty() => {
-            let words: Vec<String> = rows.iter().map(|r| r.get(0)).collect();
-            let freqs: Vec<i32> = rows.iter().map(|r| r.get(1)).collect();
-            let total: i32 = freqs.iter().sum();
-            let mut rng = thread_rng();
-            let idx = if total > 0 {
-                let dist = WeightedIndex::new(freqs.iter().map(|f| *f as f64)).unwrap();
-                dist.sample(&mut rng)
+    let rows = client.query(stmt, &[&w1, &w2])?;
+    let words: Vec<String> = rows.iter().map(|r| r.get(0)).collect();
+    let freqs: Vec<i32> = rows.iter().map(|r| r.get(1)).collect();
+    let total: i32 = freqs.iter().sum();
+    let weights = freqs.iter().map(|f| *f as f64).collect();
+    Ok((Candidates { words, weights }, total))
+}
+
+fn order1_candidates(client: &mut Client, w2: i32) -> Result<(Candidates, i32), postgres::Error> {
+    let stmt = "SELECT w.word, s.freq FROM markov_sequences_1 s JOIN markov_words w ON s.next_id = w.id WHERE s.p2 = $1 ORDER BY s.freq DESC, random() LIMIT 20";
+    let rows = client.query(stmt, &[&w2])?;
+    let words: Vec<String> = rows.iter().map(|r| r.get(0)).collect();
+    let freqs: Vec<i32> = rows.iter().map(|r| r.get(1)).collect();
+    let total: i32 = freqs.iter().sum();
+    let weights = freqs.iter().map(|f| *f as f64).collect();
+    Ok((Candidates { words, weights }, total))
+}
+
+fn unigram_candidates(client: &mut Client) -> Result<Candidates, postgres::Error> {
+    let stmt = "SELECT w.word, s.freq FROM markov_unigrams s JOIN markov_words w ON s.next_id = w.id ORDER BY s.freq DESC, random() LIMIT 20";
+    let rows = client.query(stmt, &[])?;
+    let words: Vec<String> = rows.iter().map(|r| r.get(0)).collect();
+    let freqs: Vec<i32> = rows.iter().map(|r| r.get(1)).collect();
+    let weights = freqs.iter().map(|f| *f as f64).collect();
+    Ok(Candidates { words, weights })
+}
+
+/// Applies Katz absolute discounting to `weights`: subtracts `discount` from
+/// each (floored at zero) and returns the discounted weights alongside their
+/// sum, so the caller can see how much mass fell out.
+fn discount_weights(weights: &[f64], discount: f64) -> (Vec<f64>, f64) {
+    let discounted: Vec<f64> = weights.iter().map(|w| (w - discount).max(0.0)).collect();
+    let discounted_total = discounted.iter().sum();
+    (discounted, discounted_total)
+}
+
+/// Combines a higher-order candidate set (already discounted) with a
+/// lower-order backoff distribution, the way Katz back-off redistributes the
+/// probability mass `discount`ing frees up: `alpha_mass` (the higher order's
+/// original total minus its discounted total) is spread across `backoff`'s
+/// words in proportion to their weight, excluding any word already present at
+/// the higher order so its mass isn't double-counted.
+fn blend_candidates(
+    mut words: Vec<String>,
+    mut weights: Vec<f64>,
+    alpha_mass: f64,
+    backoff: &Candidates,
+    backoff_total: f64,
+) -> Candidates {
+    if alpha_mass > 0.0 && backoff_total > 0.0 {
+        let seen: std::collections::HashSet<&str> = words.iter().map(|w| w.as_str()).collect();
+        let excl_total: f64 = backoff
+            .words
+            .iter()
+            .zip(backoff.weights.iter())
+            .filter(|(w, _)| !seen.contains(w.as_str()))
+            .map(|(_, weight)| *weight)
+            .sum();
+        if excl_total > 0.0 {
+            for (word, weight) in backoff.words.iter().zip(backoff.weights.iter()) {
+                if seen.contains(word.as_str()) {
+                    continue;
+                }
+                words.push(word.clone());
+                weights.push(alpha_mass * (weight / excl_total));
+            }
+        }
+    }
+    Candidates { words, weights }
+}
+
+fn sample(candidates: &Candidates) -> Option<String> {
+    if candidates.words.is_empty() {
+        return None;
+    }
+    let mut rng = thread_rng();
+    let total: f64 = candidates.weights.iter().sum();
+    let idx = if total > 0.0 {
+        let dist = WeightedIndex::new(&candidates.weights).unwrap();
+        dist.sample(&mut rng)
+    } else {
+        rng.gen_range(0..candidates.words.len())
+    };
+    Some(candidates.words[idx].clone())
+}
+
+/// Picks the next word given the two-word context `(w1, w2)`, backing off through
+/// progressively shorter contexts (order-2 -> order-1 -> unigram) with real Katz
+/// discounting: a constant `discount` is subtracted from every order-2 count (see
+/// [`discount_weights`]), and the resulting leftover probability mass `alpha` is
+/// redistributed onto the order-1 distribution (or the unigram distribution, if
+/// order-1 is itself unseen or too sparse) via [`blend_candidates`], rather than
+/// simply vanishing. The order-2 table is only consulted at all when its summed
+/// freq clears `BACKOFF_MIN_TOTAL`; below that it's treated as unseen and we back
+/// off outright.
+fn get_next_word_from_db(client: &mut Client, w1: i32, w2: i32, order: usize, discount: f64) -> Result<Option<String>, postgres::Error> {
+    let (order2, total2) = if order >= 2 {
+        order2_candidates(client, w1, w2)?
+    } else {
+        (Candidates { words: vec![], weights: vec![] }, 0)
+    };
+    let (order1, total1) = order1_candidates(client, w2)?;
+
+    if total2 >= BACKOFF_MIN_TOTAL {
+        let (discounted2, discounted2_total) = discount_weights(&order2.weights, discount);
+        let alpha_mass = (total2 as f64 - discounted2_total).max(0.0);
+        let blended = if alpha_mass > 0.0 {
+            if total1 >= BACKOFF_MIN_TOTAL {
+                let order1_total: f64 = order1.weights.iter().sum();
+                blend_candidates(order2.words.clone(), discounted2, alpha_mass, &order1, order1_total)
             } else {
-                rng.gen_range(0..words.len())
-            };
-            Some(words[idx].clone())
+                let unigram = unigram_candidates(client)?;
+                let unigram_total: f64 = unigram.weights.iter().sum();
+                blend_candidates(order2.words.clone(), discounted2, alpha_mass, &unigram, unigram_total)
+            }
+        } else {
+            Candidates { words: order2.words.clone(), weights: discounted2 }
+        };
+        if let Some(word) = sample(&blended) {
+            return Ok(Some(word));
         }
-        _ => None,
     }
+
+    if total1 >= BACKOFF_MIN_TOTAL {
+        if let Some(word) = sample(&order1) {
+            return Ok(Some(word));
+        }
+    }
+
+    Ok(sample(&unigram_candidates(client)?))
 }
 
-fn generate_markov_text_from_db(sentences: usize) -> String {
-    let mut client = match get_connection() {
-        Ok(c) => c,
+/// Picks the next word and looks up its id (the next `w2`) as a single
+/// pooled-connection operation so [`with_retry`] can retry the whole step
+/// together rather than leaving the word picked on a connection that then
+/// drops before its id is resolved. Returns `None` when no word, or the empty
+/// end-of-line word, comes back; resetting the context is left to the caller.
+fn advance(client: &mut Client, w1: i32, w2: i32, order: usize, discount: f64) -> Result<Option<(String, i32)>, postgres::Error> {
+    match get_next_word_from_db(client, w1, w2, order, discount)? {
+        Some(word) if !word.is_empty() => {
+            let next_w2 = get_word_id(client, &word)?;
+            Ok(Some((word, next_w2)))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn generate_markov_text_from_db(sentences: usize, order: usize, discount: f64) -> String {
+    let pool = match pool() {
+        Ok(p) => p,
         Err(_) => return "<p>Content generation unavailable.</p>".to_string(),
     };
     let mut result = String::new();
@@ -71,12 +203,12 @@ fn generate_markov_text_from_db(sentences: usize) -> String {
     let mut rng = thread_rng();
     let max_words = sentences * rng.gen_range(15..=30);
     while word_count < max_words {
-        match get_next_word_from_db(&mut client, w1, w2) {
-            Some(ref word) if !word.is_empty() => {
-                current_para.push(html_escape::encode_text(word).to_string());
+        match with_retry(pool, |conn| advance(conn, w1, w2, order, discount)) {
+            Ok(Some((word, next_w2))) => {
+                current_para.push(html_escape::encode_text(&word).to_string());
                 word_count += 1;
                 w1 = w2;
-                w2 = get_word_id(&mut client, word);
+                w2 = next_w2;
                 if [".", "!", "?"].iter().any(|p| word.ends_with(p)) && current_para.len() > 5 {
                     result.push_str("<p>");
                     result.push_str(&current_para.join(" "));
@@ -86,7 +218,7 @@ fn generate_markov_text_from_db(sentences: usize) -> String {
                     w2 = 1;
                 }
             }
-            _ => {
+            Ok(None) => {
                 if !current_para.is_empty() {
                     result.push_str("<p>");
                     result.push_str(&current_para.join(" "));
@@ -99,6 +231,13 @@ fn generate_markov_text_from_db(sentences: usize) -> String {
                     break;
                 }
             }
+            // A fatal error (not a transient one retryable by `with_retry`) means
+            // the schema or query itself is broken - stop rather than spin.
+            Err(DbError::Fatal(_)) => break,
+            // Retries were exhausted on a transient failure (e.g. the database is
+            // genuinely down) - keep whatever paragraph was generated so far
+            // instead of looping forever against an unreachable connection.
+            Err(DbError::Transient(_)) => break,
         }
     }
     if !current_para.is_empty() {
@@ -153,11 +292,9 @@ fn generate_fake_links(count: usize, depth: usize) -> Vec<String> {
     links
 }
 
-fn generate_page() -> String {
-    let content = generate_markov_text_from_db(15);
-    let links = generate_fake_links(7, 3);
+fn links_to_html(links: &[String]) -> String {
     let mut link_html = String::from("<ul>\n");
-    for link in &links {
+    for link in links {
         let text_base = link
             .split('/')
             .next_back()
@@ -176,18 +313,48 @@ fn generate_page() -> String {
         link_html.push_str(&format!("    <li><a href=\"{}\">{}</a></li>\n", link, safe));
     }
     link_html.push_str("</ul>\n");
+    link_html
+}
+
+fn render_page(content: &str, link_html: &str) -> String {
     let title = rand_string(8);
     format!("<!DOCTYPE html><html lang=\"en\"><head><meta charset=\"UTF-8\"><title>{} - System Documentation</title><meta name=\"robots\" content=\"noindex, nofollow\"></head><body><h1>{}</h1>{}<h2>Further Reading:</h2>{}<a href=\"/internal-docs/admin\" class=\"footer-link\">Admin Console</a></body></html>",
         title, title, content, link_html)
 }
 
+fn generate_page(order: usize, discount: f64) -> String {
+    let content = generate_markov_text_from_db(15, order, discount);
+    let links = generate_fake_links(7, 3);
+    render_page(&content, &links_to_html(&links))
+}
+
+#[pyfunction(signature = (order = DEFAULT_ORDER, discount = DEFAULT_DISCOUNT))]
+fn generate_dynamic_tarpit_page(order: usize, discount: f64) -> PyResult<String> {
+    Ok(generate_page(order.max(1), discount))
+}
+
+/// Renders a tarpit page whose outgoing links are deterministic neighbors of
+/// `path` in the implicit site graph (see [`graph`]), so a crawler that
+/// revisits the same path always sees the same links, and following them
+/// keeps it inside the graph rather than escaping to a dead end.
 #[pyfunction]
-fn generate_dynamic_tarpit_page() -> PyResult<String> {
-    Ok(generate_page())
+fn generate_linked_tarpit_page(path: String, seed: u64) -> PyResult<String> {
+    let content = generate_markov_text_from_db(15, DEFAULT_ORDER, DEFAULT_DISCOUNT);
+    let links = graph::linked_paths(&path, seed);
+    Ok(render_page(&content, &links_to_html(&links)))
+}
+
+/// Exports the subgraph reachable from `root` (up to `depth` hops) as
+/// Graphviz DOT so operators can visualize/verify the trap topology.
+#[pyfunction(signature = (root, depth, seed = 0))]
+fn export_tarpit_dot(root: String, depth: usize, seed: u64) -> PyResult<String> {
+    Ok(graph::export_dot(&root, depth, seed))
 }
 
 #[pymodule]
 fn tarpit_rs(_py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(generate_dynamic_tarpit_page, m)?)?;
+    m.add_function(wrap_pyfunction!(generate_linked_tarpit_page, m)?)?;
+    m.add_function(wrap_pyfunction!(export_tarpit_dot, m)?)?;
     Ok(())
 }
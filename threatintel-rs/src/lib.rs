@@ -0,0 +1,342 @@
+//! Threat-intelligence matcher: loads a structured indicator database (CIDR
+//! blocklists and User-Agent/path signature patterns, each rule carrying
+//! optional free-form tags) and scores requests against it. There is no
+//! separate ASN rule type; IPs are matched by CIDR only. The compiled ruleset
+//! lives behind an `ArcSwap` so a `reload()` call, which re-reads the source
+//! file when its mtime has changed, can hot-swap in new indicators without
+//! ever blocking an in-flight `score_request`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use arc_swap::ArcSwap;
+use once_cell::sync::Lazy;
+use pyo3::prelude::*;
+use regex::RegexSet;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize, Clone)]
+struct CidrRule {
+    cidr: String,
+    weight: f64,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct PatternRule {
+    pattern: String,
+    weight: f64,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+struct ThreatIntelFile {
+    #[serde(default)]
+    cidrs: Vec<CidrRule>,
+    #[serde(default)]
+    user_agents: Vec<PatternRule>,
+    #[serde(default)]
+    paths: Vec<PatternRule>,
+}
+
+struct CompiledCidr {
+    network: IpAddr,
+    prefix_len: u8,
+    weight: f64,
+    tags: Vec<String>,
+}
+
+fn ip_to_bits(ip: &IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(v4) => u32::from(*v4) as u128,
+        IpAddr::V6(v6) => u128::from(*v6),
+    }
+}
+
+impl CompiledCidr {
+    fn parse(rule: &CidrRule) -> Option<Self> {
+        let (addr, len) = rule.cidr.split_once('/')?;
+        let network: IpAddr = addr.parse().ok()?;
+        let prefix_len: u8 = len.parse().ok()?;
+        Some(Self {
+            network,
+            prefix_len,
+            weight: rule.weight,
+            tags: rule.tags.clone(),
+        })
+    }
+
+    fn matches(&self, ip: &IpAddr) -> bool {
+        if std::mem::discriminant(&self.network) != std::mem::discriminant(ip) {
+            return false;
+        }
+        let bits = if matches!(ip, IpAddr::V4(_)) { 32 } else { 128 };
+        if self.prefix_len > bits {
+            return false;
+        }
+        let shift = bits - self.prefix_len;
+        let mask: u128 = if shift >= 128 { 0 } else { !0u128 << shift };
+        (ip_to_bits(&self.network) & mask) == (ip_to_bits(ip) & mask)
+    }
+}
+
+/// Finds the matching CIDR with the longest (most specific) prefix, mirroring
+/// the longest-prefix-match semantics of a production IP routing table.
+fn longest_prefix_match<'a>(cidrs: &'a [CompiledCidr], ip: &IpAddr) -> Option<&'a CompiledCidr> {
+    cidrs
+        .iter()
+        .filter(|c| c.matches(ip))
+        .max_by_key(|c| c.prefix_len)
+}
+
+struct PatternSet {
+    regex_set: RegexSet,
+    weights: Vec<f64>,
+    tags: Vec<Vec<String>>,
+}
+
+impl PatternSet {
+    /// Compiles `rules` into a single `RegexSet`, failing with the offending
+    /// pattern named rather than silently falling back to an empty set: one
+    /// malformed regex used to discard every indicator in the set with no
+    /// error surfaced, which is exactly the wrong behavior when an operator
+    /// pushes a bad hot-reload rule - it should fail loudly instead of making
+    /// the scorer a silent no-op. Used for both `user_agents` and `paths`,
+    /// which share the same `PatternRule` shape.
+    fn compile(rules: &[PatternRule]) -> Result<Self, String> {
+        let patterns: Vec<&str> = rules.iter().map(|r| r.pattern.as_str()).collect();
+        let regex_set = RegexSet::new(&patterns).map_err(|e| format!("invalid pattern: {}", e))?;
+        Ok(PatternSet {
+            regex_set,
+            weights: rules.iter().map(|r| r.weight).collect(),
+            tags: rules.iter().map(|r| r.tags.clone()).collect(),
+        })
+    }
+
+    fn score(&self, haystack: &str) -> (f64, Vec<String>) {
+        let mut score = 0.0;
+        let mut tags = Vec::new();
+        for idx in self.regex_set.matches(haystack).iter() {
+            score += self.weights[idx];
+            tags.extend(self.tags[idx].iter().cloned());
+        }
+        (score, tags)
+    }
+}
+
+struct RuleSet {
+    cidrs: Vec<CompiledCidr>,
+    user_agents: PatternSet,
+    // `paths` used to compile via a literal-only Aho-Corasick automaton, which
+    // silently never matched a path signature written with regex metacharacters
+    // (e.g. `\.env$`) even though `PatternRule` is the identical shape used for
+    // `user_agents`. Compiling it through the same `PatternSet` as `user_agents`
+    // removes that trap at the cost of the literal engine's raw throughput.
+    paths: PatternSet,
+}
+
+impl RuleSet {
+    fn compile(file: &ThreatIntelFile) -> Result<Self, String> {
+        Ok(RuleSet {
+            cidrs: file.cidrs.iter().filter_map(CompiledCidr::parse).collect(),
+            user_agents: PatternSet::compile(&file.user_agents)?,
+            paths: PatternSet::compile(&file.paths)?,
+        })
+    }
+
+    fn empty() -> Self {
+        RuleSet::compile(&ThreatIntelFile::default()).expect("an empty ruleset always compiles")
+    }
+}
+
+static RULESET: Lazy<ArcSwap<RuleSet>> = Lazy::new(|| ArcSwap::from_pointee(RuleSet::empty()));
+static SOURCE_PATH: Lazy<std::sync::Mutex<Option<PathBuf>>> = Lazy::new(|| std::sync::Mutex::new(None));
+static LAST_MTIME: AtomicU64 = AtomicU64::new(0);
+
+fn mtime_secs(path: &PathBuf) -> Option<u64> {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+}
+
+fn load_rules(path: &PathBuf) -> Result<RuleSet, String> {
+    let raw = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    let parsed: ThreatIntelFile = if path.extension().and_then(|e| e.to_str()) == Some("json") {
+        serde_json::from_str(&raw).map_err(|e| format!("invalid JSON: {}", e))?
+    } else {
+        toml::from_str(&raw).map_err(|e| format!("invalid TOML: {}", e))?
+    };
+    RuleSet::compile(&parsed)
+}
+
+/// Loads the rule database from `path` and swaps it in atomically, then
+/// records `path` in `SOURCE_PATH` and its mtime in `LAST_MTIME`, which
+/// `reload()` uses to re-read the same file and to skip it while its mtime
+/// is unchanged.
+fn reload_from(path: &PathBuf) -> Result<(), String> {
+    let ruleset = load_rules(path)?;
+    RULESET.store(Arc::new(ruleset));
+    if let Some(secs) = mtime_secs(path) {
+        LAST_MTIME.store(secs, Ordering::SeqCst);
+    }
+    *SOURCE_PATH.lock().unwrap() = Some(path.clone());
+    Ok(())
+}
+
+/// Loads the threat-intel database from a TOML or JSON file (by extension)
+/// and installs it as the active ruleset.
+#[pyfunction]
+fn load_threat_db(path: String) -> PyResult<()> {
+    reload_from(&PathBuf::from(path)).map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e))
+}
+
+/// Re-reads the previously loaded file (if its mtime changed since the last
+/// load) and hot-swaps in the recompiled ruleset. Safe to call on every
+/// request; in-flight `score_request` calls keep using the ruleset snapshot
+/// they already hold.
+#[pyfunction]
+fn reload() -> PyResult<bool> {
+    let path = match SOURCE_PATH.lock().unwrap().clone() {
+        Some(p) => p,
+        None => return Ok(false),
+    };
+    let current_mtime = mtime_secs(&path).unwrap_or(0);
+    if current_mtime != 0 && current_mtime == LAST_MTIME.load(Ordering::SeqCst) {
+        return Ok(false);
+    }
+    reload_from(&path).map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e))?;
+    Ok(true)
+}
+
+fn score(ip: &str, user_agent: &str, path: &str) -> (f64, Vec<String>) {
+    let ruleset = RULESET.load();
+    let mut total = 0.0;
+    let mut tags: Vec<String> = Vec::new();
+
+    if let Ok(addr) = ip.parse::<IpAddr>() {
+        if let Some(hit) = longest_prefix_match(&ruleset.cidrs, &addr) {
+            total += hit.weight;
+            tags.extend(hit.tags.iter().cloned());
+        }
+    }
+
+    let (ua_score, ua_tags) = ruleset.user_agents.score(user_agent);
+    total += ua_score;
+    tags.extend(ua_tags);
+
+    let (path_score, path_tags) = ruleset.paths.score(path);
+    total += path_score;
+    tags.extend(path_tags);
+
+    let mut dedup_tags: Vec<String> = Vec::new();
+    let mut seen: HashMap<&str, ()> = HashMap::new();
+    for tag in &tags {
+        if seen.insert(tag.as_str(), ()).is_none() {
+            dedup_tags.push(tag.clone());
+        }
+    }
+
+    (total, dedup_tags)
+}
+
+/// Scores a request against the active threat-intel ruleset, combining any
+/// CIDR, User-Agent, and path-signature hits into a single weighted score
+/// plus the union of matched indicator tags.
+#[pyfunction]
+fn score_request(ip: String, user_agent: String, path: String) -> PyResult<(f64, Vec<String>)> {
+    Ok(score(&ip, &user_agent, &path))
+}
+
+#[pymodule]
+fn threatintel_rs(_py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(load_threat_db, m)?)?;
+    m.add_function(wrap_pyfunction!(reload, m)?)?;
+    m.add_function(wrap_pyfunction!(score_request, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::thread;
+    use std::time::Duration;
+
+    fn cidr(s: &str) -> CompiledCidr {
+        CompiledCidr::parse(&CidrRule { cidr: s.to_string(), weight: 1.0, tags: vec![] }).unwrap()
+    }
+
+    #[test]
+    fn cidr_matches_within_v4_prefix() {
+        let c = cidr("10.0.0.0/24");
+        assert!(c.matches(&"10.0.0.42".parse().unwrap()));
+        assert!(!c.matches(&"10.0.1.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn cidr_v4_and_v6_never_match_each_other() {
+        let v4 = cidr("10.0.0.0/8");
+        let v6 = cidr("fe80::/16");
+        assert!(!v4.matches(&"fe80::1".parse().unwrap()));
+        assert!(!v6.matches(&"10.1.2.3".parse().unwrap()));
+    }
+
+    #[test]
+    fn cidr_prefix_len_zero_matches_everything_of_that_family() {
+        let c = cidr("0.0.0.0/0");
+        assert!(c.matches(&"1.2.3.4".parse().unwrap()));
+        assert!(c.matches(&"255.255.255.255".parse().unwrap()));
+        assert!(!c.matches(&"::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn cidr_prefix_len_full_width_matches_only_exact_address() {
+        let c = cidr("192.168.1.1/32");
+        assert!(c.matches(&"192.168.1.1".parse().unwrap()));
+        assert!(!c.matches(&"192.168.1.2".parse().unwrap()));
+
+        let c6 = cidr("fe80::1/128");
+        assert!(c6.matches(&"fe80::1".parse().unwrap()));
+        assert!(!c6.matches(&"fe80::2".parse().unwrap()));
+    }
+
+    #[test]
+    fn path_patterns_match_as_regexes_not_just_literals() {
+        let file = ThreatIntelFile {
+            cidrs: vec![],
+            user_agents: vec![],
+            paths: vec![PatternRule { pattern: r"\.env$".to_string(), weight: 5.0, tags: vec!["dotenv".to_string()] }],
+        };
+        let ruleset = RuleSet::compile(&file).unwrap();
+        let (score, tags) = ruleset.paths.score("/app/config/.env");
+        assert_eq!(score, 5.0);
+        assert_eq!(tags, vec!["dotenv".to_string()]);
+        assert_eq!(ruleset.paths.score("/app/.env.example"), (0.0, vec![]));
+    }
+
+    #[test]
+    fn reload_is_a_no_op_when_mtime_is_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("threats.json");
+        fs::write(&path, r#"{"cidrs":[{"cidr":"10.0.0.0/8","weight":1.0}]}"#).unwrap();
+
+        load_threat_db(path.to_string_lossy().to_string()).unwrap();
+        assert!(!reload().unwrap(), "reload should no-op when the file hasn't changed");
+
+        // Bump the mtime (not just the content) far enough to be observable
+        // at `reload()`'s one-second mtime resolution, then confirm it reloads.
+        thread::sleep(Duration::from_secs(1));
+        fs::write(&path, r#"{"cidrs":[{"cidr":"10.0.0.0/8","weight":2.0}]}"#).unwrap();
+        assert!(reload().unwrap(), "reload should pick up a changed mtime");
+        assert!(!reload().unwrap(), "reload should settle back to a no-op");
+    }
+}